@@ -0,0 +1,28 @@
+// command line options
+
+// value after the option name, e.g. "german" for --language german; None if the option is not
+// given, panics if it has no value
+pub fn option<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == name)
+        .map(|pos| args.get(pos + 1).expect("option needs a value").as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::*;
+
+    #[test]
+    fn test_option() {
+        let args = ["1plus", "--language", "german", "--zero"].map(String::from);
+
+        assert_eq!(option(&args, "--language"), Some("german"));
+        assert_eq!(option(&args, "--vocabulary"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "option needs a value")]
+    fn test_option_without_value() {
+        option(&["--zero".to_string()], "--zero");
+    }
+}
@@ -1,20 +1,69 @@
+use aoc::calibration::{
+    calibrate, extraction_from_args, Extraction, ExtractionError, Missing, Scanner, Vocabulary,
+};
+use std::env;
 use std::fs;
 
+// first and last digit of the line by default, None if there is none, an error if the digits
+// give no value
+fn get_calibration(
+    scanner: &Scanner,
+    extraction: &dyn Extraction,
+    line: &str,
+) -> Result<Option<u64>, ExtractionError> {
+    extraction.value(&scanner.numbers(line))
+}
+
 fn main() {
     let file_path = "1.input";
 
-    let input = fs::read_to_string(file_path)
-        .expect("Should have been able to read the file");
+    let input = fs::read_to_string(file_path).expect("Should have been able to read the file");
+    let args = env::args().collect::<Vec<String>>();
+
+    let scanner = Scanner::new(&Vocabulary::digits());
+    let extraction = extraction_from_args(&args);
+    let calibration = calibrate(&input, Missing::from_args(&args), |line| {
+        get_calibration(&scanner, extraction.as_ref(), line)
+    })
+    .expect("Should have been a value for every line");
 
+    println!("{}", calibration.sum());
+    if !calibration.missing.is_empty() {
+        println!(
+            "Lines without digits: {}",
+            calibration
+                .missing
+                .iter()
+                .map(|number| number.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+    }
+}
 
-    let result: i32 = input.split_terminator("\n")
-        .map(|x| -> i32 {
-            let nums: Vec<&str> = x.matches(char::is_numeric).collect();
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use aoc::calibration::{FirstLast, LastDigits};
 
-            nums.iter().nth(0).unwrap().parse::<i32>().unwrap() * 10 +
-                nums.iter().last().unwrap().parse::<i32>().unwrap()
+    #[test]
+    fn test_calibrate() {
+        let input = fs::read_to_string("1plus-sample.input")
+            .expect("Should have been able to read the file");
+        let scanner = Scanner::new(&Vocabulary::digits());
+        let calibration = calibrate(&input, Missing::Report, |line| {
+            get_calibration(&scanner, &FirstLast, line)
         })
-        .sum();
+        .unwrap();
+
+        // eightwothree has no digit
+        assert_eq!(calibration.sum(), 11 + 22 + 33 + 42 + 24 + 77);
+        assert_eq!(calibration.missing, [2]);
 
-    println!("{}", result);
+        let calibration = calibrate(&input, Missing::Skip, |line| {
+            get_calibration(&scanner, &LastDigits { k: 3 }, line)
+        })
+        .unwrap();
+        assert_eq!(calibration.sum(), 1 + 2 + 3 + 42 + 234 + 7);
+    }
 }
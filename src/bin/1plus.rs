@@ -1,52 +1,293 @@
-use regex::Regex;
+use aoc::args::option;
+use aoc::calibration::{
+    calibrate, extraction_from_args, Extraction, ExtractionError, Missing, Scanner, Token,
+    Vocabulary,
+};
+use std::env;
 use std::fs;
 
-fn get_number(value: &str) -> i32 {
-    match value {
-        "one" => 1,
-        "two" => 2,
-        "three" => 3,
-        "four" => 4,
-        "five" => 5,
-        "six" => 6,
-        "seven" => 7,
-        "eight" => 8,
-        "nine" => 9,
-        _ => value.parse::<i32>().unwrap(),
+// first and last digit of the line by default, written as digit or word. "8twoneh" is 81, the
+// words may overlap, so the scanner finds all of them in one pass. None if there is no digit, an
+// error if the digits give no value
+fn get_calibration(
+    scanner: &Scanner,
+    extraction: &dyn Extraction,
+    line: &str,
+) -> Result<Option<u64>, ExtractionError> {
+    extraction.value(&scanner.numbers(line))
+}
+
+// digits and the words of --language <name>[,<name>...] (english by default), --zero adds the
+// words for zero and --vocabulary <file> adds the words of a file
+fn get_vocabulary(args: &[String]) -> Vocabulary {
+    let value = |name: &str| option(args, name);
+    let zero = args.iter().any(|arg| arg == "--zero");
+    let mut vocabulary = Vocabulary::digits();
+
+    for language in value("--language").unwrap_or("english").split(',') {
+        vocabulary = vocabulary
+            .with(Vocabulary::language(language, zero).expect("Should have been a known language"));
+    }
+    if let Some(file) = value("--vocabulary") {
+        let text = fs::read_to_string(file).expect("Should have been able to read the vocabulary");
+        vocabulary =
+            vocabulary.with(Vocabulary::parse(&text).expect("Should have been a valid vocabulary"));
+    }
+    vocabulary
+}
+
+// how both parts read a line, part 1 only knows digits
+struct Explanation<'a> {
+    // counted from 1
+    number: usize,
+    line: &'a str,
+    part1: Option<u32>,
+    // first and last token of part 2
+    tokens: Option<(Token, Token)>,
+}
+
+impl<'a> Explanation<'a> {
+    fn new(number: usize, line: &'a str, digits: &Scanner, scanner: &Scanner) -> Self {
+        Explanation {
+            number,
+            line,
+            part1: digits
+                .first_last(line)
+                .map(|(first, last)| first.value * 10 + last.value),
+            tokens: scanner.first_last(line),
+        }
+    }
+
+    fn part2(&self) -> Option<u32> {
+        self.tokens
+            .map(|(first, last)| first.first_digit() * 10 + last.last_digit())
+    }
+
+    fn differs(&self) -> bool {
+        self.part1 != self.part2()
+    }
+}
+
+fn explain<'a>(input: &'a str, scanner: &Scanner) -> Vec<Explanation<'a>> {
+    let digits = Scanner::new(&Vocabulary::digits());
+
+    input
+        .split_terminator('\n')
+        .enumerate()
+        .map(|(pos, line)| Explanation::new(pos + 1, line, &digits, scanner))
+        .collect()
+}
+
+// one row per line, * marks the lines where the parts differ
+fn explain_table(explanations: &[Explanation]) -> String {
+    let value = |value: Option<u32>| value.map_or("-".to_string(), |value| value.to_string());
+    let token = |token: &Token| {
+        format!(
+            "{} {} {}..{}",
+            token.source.name(),
+            token.value,
+            token.start,
+            token.end
+        )
+    };
+    let mut result = format!(
+        "{:>5} {:>5} {:>5} {:<16} {:<16} line\n",
+        "line", "part1", "part2", "first", "last"
+    );
+
+    for explanation in explanations {
+        let (first, last) = match &explanation.tokens {
+            Some((first, last)) => (token(first), token(last)),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        result += &format!(
+            "{:>5} {:>5} {:>5} {:<16} {:<16} {}{}\n",
+            explanation.number,
+            value(explanation.part1),
+            value(explanation.part2()),
+            first,
+            last,
+            explanation.line,
+            if explanation.differs() { " *" } else { "" }
+        );
+    }
+    result
+}
+
+fn json_string(value: &str) -> String {
+    let mut result = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => result += "\\\"",
+            '\\' => result += "\\\\",
+            c if c.is_control() => result += &format!("\\u{:04x}", c as u32),
+            c => result.push(c),
+        }
     }
+    result + "\""
 }
 
-fn reverse(s: &str) -> String {
-    s.chars().rev().collect()
+// one object per line, the values are null without digits
+fn explain_json(explanations: &[Explanation]) -> String {
+    let value = |value: Option<u32>| value.map_or("null".to_string(), |value| value.to_string());
+    let token = |token: &Token| {
+        format!(
+            "{{\"value\":{},\"start\":{},\"end\":{},\"source\":\"{}\"}}",
+            token.value,
+            token.start,
+            token.end,
+            token.source.name()
+        )
+    };
+    let lines = explanations
+        .iter()
+        .map(|explanation| {
+            let (first, last) = match &explanation.tokens {
+                Some((first, last)) => (token(first), token(last)),
+                None => ("null".to_string(), "null".to_string()),
+            };
+            format!(
+                "{{\"line\":{},\"text\":{},\"part1\":{},\"part2\":{},\"first\":{},\"last\":{},\"differs\":{}}}",
+                explanation.number,
+                json_string(explanation.line),
+                value(explanation.part1),
+                value(explanation.part2()),
+                first,
+                last,
+                explanation.differs()
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(",");
+
+    format!("[{}]\n", lines)
 }
 
 fn main() {
     let file_path = "1.input";
 
     let input = fs::read_to_string(file_path).expect("Should have been able to read the file");
+    let args = env::args().collect::<Vec<String>>();
 
-    let number_regex = Regex::new(r"(one|two|three|four|five|six|seven|eight|nine|[0-9])").unwrap();
-    // "8twoneh" contains 8, two and one. A normal regex only finds 8 and "two", but not "one".
-    // as there is no regex from back, the string is reversed and a reverse regex is used
-    let number_regex_reverse = Regex::new(r"(eno|owt|eerht|ruof|evif|xis|neves|thgie|enin|[0-9])").unwrap();
-
-    let result: i32 = input
-        .split_terminator("\n")
-        .map(|line| -> i32 {
-            let result = get_number(&number_regex.captures_iter(line).nth(0).unwrap()[0]) * 10
-                + get_number(
-                    &reverse(
-                        &number_regex_reverse.captures_iter(
-                            &reverse(line)
-                        ).nth(0).unwrap()[0]
-                    )
-                );
-
-            println!("line: {}, reverse: {}, number: {}", line, reverse(line), result);
-
-            return result;
-        })
-        .sum();
+    let scanner = Scanner::new(&get_vocabulary(&args));
+
+    // --explain table|json shows how each line is read instead of the result
+    if let Some(format) = option(&args, "--explain") {
+        let explanations = explain(&input, &scanner);
+        match format {
+            "table" => print!("{}", explain_table(&explanations)),
+            "json" => print!("{}", explain_json(&explanations)),
+            _ => panic!("--explain needs table or json"),
+        }
+        return;
+    }
+
+    let extraction = extraction_from_args(&args);
+    let calibration = calibrate(&input, Missing::from_args(&args), |line| {
+        get_calibration(&scanner, extraction.as_ref(), line)
+    })
+    .expect("Should have been a value for every line");
+
+    println!("Result: {}", calibration.sum());
+    if !calibration.missing.is_empty() {
+        println!(
+            "Lines without digits: {}",
+            calibration
+                .missing
+                .iter()
+                .map(|number| number.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use aoc::calibration::{FirstLast, LargestPair};
 
-    println!("Result: {}", result);
+    #[test]
+    fn test_get_calibration() {
+        let input = fs::read_to_string("1plus-sample.input")
+            .expect("Should have been able to read the file");
+        let scanner = Scanner::new(&get_vocabulary(&[]));
+
+        assert_eq!(
+            input
+                .split_terminator('\n')
+                .map(|line| get_calibration(&scanner, &FirstLast, line))
+                .collect::<Vec<Result<Option<u64>, ExtractionError>>>(),
+            [29, 83, 13, 24, 42, 14, 76].map(|value| Ok(Some(value)))
+        );
+    }
+
+    #[test]
+    fn test_get_vocabulary() {
+        let args = ["1plus", "--language", "german,french", "--zero"].map(String::from);
+        let scanner = Scanner::new(&get_vocabulary(&args));
+
+        assert_eq!(
+            get_calibration(&scanner, &FirstLast, "nullxhuit"),
+            Ok(Some(8))
+        );
+        assert_eq!(
+            get_calibration(&scanner, &FirstLast, "dreiun"),
+            Ok(Some(31))
+        );
+        assert_eq!(get_calibration(&scanner, &FirstLast, "one2"), Ok(Some(22)));
+        assert_eq!(get_calibration(&scanner, &FirstLast, "nothing"), Ok(None));
+        assert_eq!(
+            get_calibration(&scanner, &LargestPair, "dreiun5"),
+            Ok(Some(35))
+        );
+
+        let scanner = Scanner::new(&Vocabulary::parse("twenty one = 21\n").unwrap());
+        assert_eq!(
+            get_calibration(&scanner, &FirstLast, "twenty one"),
+            Ok(Some(21))
+        );
+    }
+
+    #[test]
+    fn test_explain() {
+        let scanner = Scanner::new(&get_vocabulary(&[]));
+        let explanations = explain("two1nine\n7pqrst\nabc\n", &scanner);
+
+        assert_eq!(
+            explanations
+                .iter()
+                .map(|explanation| (
+                    explanation.part1,
+                    explanation.part2(),
+                    explanation.differs()
+                ))
+                .collect::<Vec<(Option<u32>, Option<u32>, bool)>>(),
+            [
+                (Some(11), Some(29), true),
+                (Some(77), Some(77), false),
+                (None, None, false)
+            ]
+        );
+
+        assert_eq!(
+            explain_table(&explanations).lines().collect::<Vec<&str>>(),
+            [
+                " line part1 part2 first            last             line",
+                "    1    11    29 word 2 0..3      word 9 4..8      two1nine *",
+                "    2    77    77 digit 7 0..1     digit 7 0..1     7pqrst",
+                "    3     -     - -                -                abc",
+            ]
+        );
+
+        assert_eq!(
+            explain_json(&explanations[..1]),
+            "[{\"line\":1,\"text\":\"two1nine\",\"part1\":11,\"part2\":29,\
+            \"first\":{\"value\":2,\"start\":0,\"end\":3,\"source\":\"word\"},\
+            \"last\":{\"value\":9,\"start\":4,\"end\":8,\"source\":\"word\"},\
+            \"differs\":true}]\n"
+        );
+        assert!(explain_json(&explanations).contains("\"first\":null"));
+        assert_eq!(json_string("a\"b\\"), "\"a\\\"b\\\\\"");
+    }
 }
@@ -1,24 +1,34 @@
+use aoc::args::option;
 use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fmt;
 use std::fs;
 use std::iter::Sum;
 use std::ops::Add;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, PartialEq, Default)]
+// cubes of any number of colors, colors without cubes are not stored
+#[derive(Debug, PartialEq, Default, Clone)]
 struct Cubes {
-    red: u32,
-    green: u32,
-    blue: u32,
+    counts: BTreeMap<String, u32>,
+}
+
+impl<const N: usize> From<[(&str, u32); N]> for Cubes {
+    fn from(counts: [(&str, u32); N]) -> Self {
+        counts
+            .into_iter()
+            .map(|(color, count)| Cubes::one(color, count))
+            .sum()
+    }
 }
 
 impl Add for Cubes {
     type Output = Self;
 
     fn add(self, other: Self) -> Self {
-        Self {
-            red: self.red + other.red,
-            green: self.green + other.green,
-            blue: self.blue + other.blue,
-        }
+        self.checked_add(&other)
+            .expect("Should have been few enough cubes to add up")
     }
 }
 
@@ -36,7 +46,30 @@ impl Sum<Cubes> for Cubes {
     }
 }
 
+impl fmt::Display for Cubes {
+    // "6 blue, 20 red", colors in alphabetical order
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.counts.is_empty() {
+            return write!(f, "no cubes");
+        }
+        let counts = self
+            .counts
+            .iter()
+            .map(|(color, count)| format!("{} {}", count, color))
+            .collect::<Vec<String>>();
+        write!(f, "{}", counts.join(", "))
+    }
+}
+
 impl Cubes {
+    fn one(color: &str, count: u32) -> Cubes {
+        let mut counts = BTreeMap::new();
+        if count > 0 {
+            counts.insert(color.to_string(), count);
+        }
+        Cubes { counts }
+    }
+
     // get one set
     // example input: "3 blue, 4 red"
     fn get_cubes_one(value: &str) -> Cubes {
@@ -46,21 +79,7 @@ impl Cubes {
                 let parts: Vec<&str> = x.split(' ').collect();
                 assert_eq!(parts.len(), 2);
                 let count = parts[0].parse::<u32>().unwrap();
-                match parts[1] {
-                    "red" => Cubes {
-                        red: count,
-                        ..Default::default()
-                    },
-                    "green" => Cubes {
-                        green: count,
-                        ..Default::default()
-                    },
-                    "blue" => Cubes {
-                        blue: count,
-                        ..Default::default()
-                    },
-                    _ => panic!("unknown color {}", parts[1]),
-                }
+                Cubes::one(parts[1], count)
             })
             .sum::<Cubes>()
     }
@@ -74,16 +93,99 @@ impl Cubes {
             .collect()
     }
 
+    // None if a color gets more cubes than a u32 holds
+    fn checked_add(mut self, other: &Cubes) -> Option<Cubes> {
+        for (color, &count) in &other.counts {
+            let total = self.counts.entry(color.clone()).or_default();
+            *total = total.checked_add(count)?;
+        }
+        Some(self)
+    }
+
+    fn get(&self, color: &str) -> u32 {
+        self.counts.get(color).copied().unwrap_or(0)
+    }
+
+    fn colors(&self) -> impl Iterator<Item = &str> {
+        self.counts.keys().map(String::as_str)
+    }
+
+    // apply f to the counts of every color in either set
+    fn combine(&self, other: &Self, f: impl Fn(u32, u32) -> u32) -> Self {
+        self.colors()
+            .chain(other.colors())
+            .collect::<BTreeSet<&str>>()
+            .into_iter()
+            .map(|color| Cubes::one(color, f(self.get(color), other.get(color))))
+            .sum()
+    }
+
     fn max(&self, other: &Self) -> Self {
-        Cubes {
-            red: self.red.max(other.red),
-            green: self.green.max(other.green),
-            blue: self.blue.max(other.blue),
+        self.combine(other, u32::max)
+    }
+
+    fn min(&self, other: &Self) -> Self {
+        self.combine(other, u32::min)
+    }
+
+    // number of cubes of all colors, wide enough for any number of colors
+    fn count(&self) -> u64 {
+        self.counts.values().map(|&count| count as u64).sum()
+    }
+
+    // the bag of the puzzle, it has no cubes of other colors
+    fn bag() -> Cubes {
+        Cubes::from([("red", 12), ("green", 13), ("blue", 14)])
+    }
+
+    // bag limits like "red=12, green=13", one limit per item
+    fn parse_bag<'a>(items: impl Iterator<Item = &'a str>) -> Result<Cubes, BagError> {
+        let mut bag = Cubes::default();
+        for item in items.map(str::trim).filter(|item| !item.is_empty()) {
+            let (color, count) = item
+                .split_once('=')
+                .ok_or_else(|| BagError::InvalidLimit(item.to_string()))?;
+            let count = count
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| BagError::InvalidLimit(item.to_string()))?;
+            if color.trim().is_empty() {
+                return Err(BagError::InvalidLimit(item.to_string()));
+            }
+            // a color may be given more than once, its limits add up
+            bag = bag
+                .checked_add(&Cubes::one(color.trim(), count))
+                .ok_or_else(|| BagError::InvalidLimit(item.to_string()))?;
         }
+        Ok(bag)
+    }
+
+    // no color has more cubes than the bag
+    fn fits(&self, bag: &Cubes) -> bool {
+        self.counts
+            .iter()
+            .all(|(color, &count)| count <= bag.get(color))
     }
 
-    fn get_max_cubes(value: &str) -> Cubes {
-        Self::get_cubes_all(value).iter().fold(
+    // product over the given colors, a missing color makes it 0; None if it does not fit
+    fn power<'a>(&self, colors: impl IntoIterator<Item = &'a str>) -> Option<u64> {
+        colors.into_iter().try_fold(1_u64, |power, color| {
+            power.checked_mul(self.get(color) as u64)
+        })
+    }
+}
+
+// a game with every draw in the order they were made
+#[derive(Debug, PartialEq)]
+struct Game {
+    id: u32,
+    draws: Vec<Cubes>,
+}
+
+impl Game {
+    // fewest cubes of each color the bag must have held
+    fn max(&self) -> Cubes {
+        self.draws.iter().fold(
             Cubes {
                 ..Default::default()
             },
@@ -91,53 +193,1088 @@ impl Cubes {
         )
     }
 
-    fn is_valid(&self) -> bool {
-        self.red <= 12 && self.green <= 13 && self.blue <= 14
+    // fewest cubes of each color in any draw
+    fn min(&self) -> Cubes {
+        match self.draws.split_first() {
+            Some((first, rest)) => rest.iter().fold(first.clone(), |a, b| a.min(b)),
+            None => Cubes {
+                ..Default::default()
+            },
+        }
+    }
+
+    // all cubes of a color drawn over the game, wide enough for any number of draws
+    fn total_of(&self, color: &str) -> u64 {
+        self.draws.iter().map(|draw| draw.get(color) as u64).sum()
+    }
+
+    // all cubes drawn over the game, None if a color has more than fit into the counts
+    fn total(&self) -> Option<Cubes> {
+        self.draws
+            .iter()
+            .flat_map(|draw| draw.colors())
+            .collect::<BTreeSet<&str>>()
+            .into_iter()
+            .map(|color| Some(Cubes::one(color, self.total_of(color).try_into().ok()?)))
+            .sum()
+    }
+
+    // draws with more cubes than the bag holds, counted from 0
+    fn invalid_draws(&self, bag: &Cubes) -> Vec<usize> {
+        (0..self.draws.len())
+            .filter(|&x| !self.draws[x].fits(bag))
+            .collect()
+    }
+
+    fn is_valid(&self, bag: &Cubes) -> bool {
+        self.invalid_draws(bag).is_empty()
+    }
+}
+
+// cursor over the bytes of a line, nothing is allocated until a color is stored
+struct GameParser<'a> {
+    line: &'a str,
+    pos: usize,
+}
+
+impl<'a> GameParser<'a> {
+    fn literal(&mut self, literal: &[u8]) -> Option<()> {
+        if self.line.as_bytes()[self.pos..].starts_with(literal) {
+            self.pos += literal.len();
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        let mut number: u32 = 0;
+        while let Some(&b) = self
+            .line
+            .as_bytes()
+            .get(self.pos)
+            .filter(|b| b.is_ascii_digit())
+        {
+            number = number.checked_mul(10)?.checked_add((b - b'0') as u32)?;
+            self.pos += 1;
+        }
+        (self.pos > start).then_some(number)
+    }
+
+    // a color runs until the next separator
+    fn color(&mut self) -> Option<&'a str> {
+        let start = self.pos;
+        while self
+            .line
+            .as_bytes()
+            .get(self.pos)
+            .is_some_and(|b| !b" ,;".contains(b))
+        {
+            self.pos += 1;
+        }
+        (self.pos > start).then(|| &self.line[start..self.pos])
+    }
+
+    // example input: "3 blue, 4 red"
+    fn draw(&mut self) -> Option<Cubes> {
+        let mut draw = Cubes::default();
+        loop {
+            let count = self.number()?;
+            self.literal(b" ")?;
+            let color = self.color()?;
+            if count > 0 {
+                match draw.counts.get_mut(color) {
+                    Some(total) => *total = total.checked_add(count)?,
+                    None => {
+                        draw.counts.insert(color.to_string(), count);
+                    }
+                }
+            }
+            if self.literal(b", ").is_none() {
+                return Some(draw);
+            }
+        }
     }
 
-    fn power(&self) -> u32 {
-        self.red * self.green * self.blue
+    fn game(&mut self) -> Option<Game> {
+        self.literal(b"Game ")?;
+        let id = self.number()?;
+        self.literal(b": ")?;
+        let mut draws = vec![self.draw()?];
+        while self.literal(b"; ").is_some() {
+            draws.push(self.draw()?);
+        }
+        (self.pos == self.line.len()).then_some(Game { id, draws })
     }
 }
 
-fn get_game(value: &str) -> (u32, Cubes) {
+// example input: "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green", None if the line is
+// not a game
+fn parse_game(line: &str) -> Option<Game> {
+    GameParser { line, pos: 0 }.game()
+}
+
+fn get_game(value: &str) -> Game {
+    parse_game(value).expect("Should have been a game")
+}
+
+// the parser before parse_game, kept to compare with
+fn get_game_regex(value: &str) -> Game {
     let re = Regex::new(r"^Game ([0-9]+): (.*)$").unwrap();
     let caps = re.captures(value).unwrap();
-    (
-        caps[1].parse::<u32>().unwrap(),
-        Cubes::get_max_cubes(&caps[2]),
-    )
+    Game {
+        id: caps[1].parse::<u32>().unwrap(),
+        draws: Cubes::get_cubes_all(&caps[2]),
+    }
+}
+
+fn get_all_games(value: &str) -> Vec<Game> {
+    value.split_terminator('\n').map(get_game).collect()
 }
 
-fn get_all_games(value: &str) -> Vec<(u32, Cubes)> {
-    value.split_terminator("\n").map(|x| get_game(x)).collect()
+// every color of the games, in alphabetical order
+fn get_colors(games: &[Game]) -> BTreeSet<&str> {
+    games
+        .iter()
+        .flat_map(|game| game.draws.iter().flat_map(|draw| draw.colors()))
+        .collect()
 }
 
-fn get_valid_game_sum(value: &str) -> u32 {
+fn get_valid_game_sum(value: &str, bag: &Cubes) -> u32 {
     get_all_games(value)
         .iter()
-        .filter(|(_id, cubes)| cubes.is_valid())
-        .map(|(id, _cubes)| id)
+        .filter(|game| game.is_valid(bag))
+        .map(|game| game.id)
         .sum()
 }
 
-fn get_game_power_sum(value: &str) -> u32 {
-    get_all_games(value)
+// the power is taken over every color of the input
+fn get_game_power_sum(value: &str) -> u64 {
+    let games = get_all_games(value);
+    let colors = get_colors(&games);
+    games
         .iter()
-        .map(|(_id, cubes)| cubes.power())
+        .map(|game| {
+            game.max()
+                .power(colors.iter().copied())
+                .expect("Should have been a power that fits")
+        })
         .sum()
 }
 
+#[derive(Debug, PartialEq)]
+enum BagError {
+    InvalidLimit(String),
+}
+
+// smallest bag that makes every game valid
+fn get_minimal_bag(games: &[Game]) -> Cubes {
+    games.iter().fold(Cubes::default(), |a, b| a.max(&b.max()))
+}
+
+// bag with the fewest cubes that makes at least k games valid, None if there are fewer games
+fn get_minimal_bag_for(games: &[Game], k: usize) -> Option<Cubes> {
+    if k > games.len() {
+        return None;
+    }
+    if k == 0 {
+        return Some(Cubes::default());
+    }
+    let maxes = games.iter().map(|game| game.max()).collect::<Vec<Cubes>>();
+    let colors = get_colors(games).into_iter().collect::<Vec<&str>>();
+    let mut best = None;
+    search_bag(
+        &maxes.iter().collect::<Vec<&Cubes>>(),
+        &colors,
+        k,
+        Cubes::default(),
+        &mut best,
+    );
+    best
+}
+
+// tries every limit of the first color the games need, the last color takes the k-th smallest
+// count of the games that are left
+fn search_bag(maxes: &[&Cubes], colors: &[&str], k: usize, bag: Cubes, best: &mut Option<Cubes>) {
+    let better = |bag: &Cubes, best: &Option<Cubes>| {
+        best.as_ref().is_none_or(|best| bag.count() < best.count())
+    };
+    match colors {
+        [] => {
+            if better(&bag, best) {
+                *best = Some(bag);
+            }
+        }
+        [color] => {
+            let mut counts = maxes.iter().map(|max| max.get(color)).collect::<Vec<u32>>();
+            counts.sort();
+            let bag = bag + Cubes::one(color, counts[k - 1]);
+            if better(&bag, best) {
+                *best = Some(bag);
+            }
+        }
+        [color, rest @ ..] => {
+            let limits = maxes
+                .iter()
+                .map(|max| max.get(color))
+                .collect::<BTreeSet<u32>>();
+            for limit in limits {
+                let bag = bag.clone() + Cubes::one(color, limit);
+                // the limits only grow
+                if !better(&bag, best) {
+                    break;
+                }
+                let left = maxes
+                    .iter()
+                    .filter(|max| max.get(color) <= limit)
+                    .copied()
+                    .collect::<Vec<&Cubes>>();
+                if left.len() >= k {
+                    search_bag(&left, rest, k, bag, best);
+                }
+            }
+        }
+    }
+}
+
+// the limits of a color below the bag at which valid games become invalid, the other limits
+// stay as they are
+fn get_tightening(games: &[Game], bag: &Cubes, color: &str) -> Vec<(u32, Vec<u32>)> {
+    let mut steps: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+    for game in games.iter().filter(|game| game.is_valid(bag)) {
+        let count = game.max().get(color);
+        if count > 0 {
+            steps.entry(count - 1).or_default().push(game.id);
+        }
+    }
+    steps.into_iter().rev().collect()
+}
+
+fn get_ids(games: &[Game], bag: &Cubes) -> String {
+    games
+        .iter()
+        .filter(|game| game.is_valid(bag))
+        .map(|game| game.id.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// --bag red=12,green=13,blue=14 or --bag-file <file> with one limit per line, the puzzle bag
+// otherwise
+fn get_bag(args: &[String]) -> Cubes {
+    let value = |name: &str| option(args, name);
+    if let Some(limits) = value("--bag") {
+        return Cubes::parse_bag(limits.split(',')).expect("Should have been valid bag limits");
+    }
+    if let Some(file) = value("--bag-file") {
+        let text = fs::read_to_string(file).expect("Should have been able to read the bag");
+        return Cubes::parse_bag(
+            text.lines()
+                .filter(|line| !line.trim_start().starts_with('#')),
+        )
+        .expect("Should have been valid bag limits");
+    }
+    Cubes::bag()
+}
+
+// how the cubes of a draw are taken from the bag, the cubes go back after each draw
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Sampling {
+    WithReplacement,
+    WithoutReplacement,
+}
+
+// drop in log likelihood that stays within the bounds, half the 95% quantile of chi-square
+const CONFIDENCE: f64 = 1.92;
+
+// ln of n choose k, n >= k
+fn ln_choose(n: u32, k: u32) -> f64 {
+    (0..k).map(|i| ((n - i) as f64 / (i + 1) as f64).ln()).sum()
+}
+
+impl Sampling {
+    fn from_name(name: &str) -> Option<Sampling> {
+        match name {
+            "with" => Some(Sampling::WithReplacement),
+            "without" => Some(Sampling::WithoutReplacement),
+            _ => None,
+        }
+    }
+
+    // fewest cubes of the color the bag can have to give the draws
+    fn minimum(&self, draws: &[Cubes], color: &str) -> u32 {
+        let counts = draws.iter().map(|draw| draw.get(color));
+        match self {
+            Sampling::WithReplacement => counts.max().map_or(0, |count| count.min(1)),
+            Sampling::WithoutReplacement => counts.max().unwrap_or(0),
+        }
+    }
+
+    // what one more cube of the color adds to the log likelihood, worked out on its own as the
+    // difference of the log likelihoods loses too much of it for big bags
+    fn gain(&self, draws: &[Cubes], color: &str, count: u32) -> f64 {
+        if count < self.minimum(draws, color) {
+            return self.log_likelihood(draws, color, count + 1)
+                - self.log_likelihood(draws, color, count);
+        }
+        match self {
+            Sampling::WithReplacement => draws
+                .iter()
+                .map(|draw| match draw.get(color) {
+                    0 => 0.0,
+                    drawn => drawn as f64 * (1.0 / count as f64).ln_1p(),
+                })
+                .sum(),
+            // ln of (count + 1 choose drawn) / (count choose drawn)
+            Sampling::WithoutReplacement => draws
+                .iter()
+                .map(|draw| {
+                    let drawn = draw.get(color) as f64;
+                    (drawn / (count as f64 + 1.0 - drawn)).ln_1p()
+                })
+                .sum(),
+        }
+    }
+
+    // log likelihood of the draws up to a constant for a bag size, counts in the order of colors
+    fn total(&self, draws: &[Cubes], colors: &[&str], counts: &[u32]) -> f64 {
+        (0..colors.len())
+            .map(|x| self.log_likelihood(draws, colors[x], counts[x]))
+            .sum()
+    }
+
+    // the part of the log likelihood of the draws that depends on the count of the color, the
+    // parts of all colors add up to the log likelihood up to a constant for a bag size
+    fn log_likelihood(&self, draws: &[Cubes], color: &str, count: u32) -> f64 {
+        if count < self.minimum(draws, color) {
+            return f64::NEG_INFINITY;
+        }
+        match self {
+            Sampling::WithReplacement => draws
+                .iter()
+                .map(|draw| match draw.get(color) {
+                    0 => 0.0,
+                    drawn => drawn as f64 * (count as f64).ln(),
+                })
+                .sum(),
+            Sampling::WithoutReplacement => draws
+                .iter()
+                .map(|draw| ln_choose(count, draw.get(color)))
+                .sum(),
+        }
+    }
+}
+
+// most likely bag of a game and the counts of each color within the confidence bounds
+#[derive(Debug, PartialEq)]
+struct Estimate {
+    bag: Cubes,
+    bounds: BTreeMap<String, (u32, u32)>,
+}
+
+// most likely bag of the size with the colors of the draws, counts in the order of colors, and
+// its log likelihood. None if no such bag gives the draws
+fn allocate(
+    draws: &[Cubes],
+    colors: &[&str],
+    sampling: Sampling,
+    size: u32,
+) -> Option<(Vec<u32>, f64)> {
+    let minimums = colors
+        .iter()
+        .map(|&color| sampling.minimum(draws, color))
+        .collect::<Vec<u32>>();
+    if minimums.iter().map(|&count| count as u64).sum::<u64>() > size as u64 {
+        return None;
+    }
+
+    // the bag has about as many cubes of a color as its share of the drawn cubes, exactly so
+    // with replacement and up to the size of the draws without
+    let drawn = colors
+        .iter()
+        .map(|&color| draws.iter().map(|draw| draw.get(color) as u128).sum())
+        .collect::<Vec<u128>>();
+    let all = drawn.iter().sum::<u128>().max(1);
+    let mut counts = (0..colors.len())
+        .map(|x| ((size as u128 * drawn[x] / all) as u32).max(minimums[x]))
+        .collect::<Vec<u32>>();
+    let mut count = counts.iter().map(|&count| count as u64).sum::<u64>();
+
+    // the color that gains the most by one more cube, and the one other than except that loses
+    // the least by one less
+    let gain = |x: usize, count: u32| sampling.gain(draws, colors[x], count);
+    let most = |counts: &[u32]| {
+        (0..colors.len())
+            .filter(|&x| counts[x] < size)
+            .max_by(|&a, &b| gain(a, counts[a]).total_cmp(&gain(b, counts[b])))
+    };
+    let least = |counts: &[u32], except: Option<usize>| {
+        (0..colors.len())
+            .filter(|&y| counts[y] > minimums[y] && Some(y) != except)
+            .min_by(|&a, &b| gain(a, counts[a] - 1).total_cmp(&gain(b, counts[b] - 1)))
+    };
+    while count < size as u64 {
+        let x = most(&counts)?;
+        counts[x] += 1;
+        count += 1;
+    }
+    while count > size as u64 {
+        let x = least(&counts, None)?;
+        counts[x] -= 1;
+        count -= 1;
+    }
+
+    // the log likelihood is concave in each count, so moving cubes while that gains something
+    // ends at the best bag, only a few moves away from the start
+    while let Some(x) = most(&counts) {
+        match least(&counts, Some(x)) {
+            Some(y) if gain(x, counts[x]) > gain(y, counts[y] - 1) => {
+                counts[x] += 1;
+                counts[y] -= 1;
+            }
+            _ => break,
+        }
+    }
+    let likelihood = sampling.total(draws, colors, &counts);
+    (likelihood != f64::NEG_INFINITY).then_some((counts, likelihood))
+}
+
+// maximum likelihood bag with size cubes that only has the colors seen in the game, the bounds
+// of a color are the counts whose best bag is within CONFIDENCE of the most likely one
+fn estimate_bag(game: &Game, sampling: Sampling, size: u32) -> Option<Estimate> {
+    let draws = &game.draws;
+    let colors = get_colors(std::slice::from_ref(game))
+        .into_iter()
+        .collect::<Vec<&str>>();
+    let (best, likelihood) = allocate(draws, &colors, sampling, size)?;
+
+    let mut bounds = BTreeMap::new();
+    for x in 0..colors.len() {
+        let others = [&colors[..x], &colors[x + 1..]].concat();
+        // the best bag with count cubes of the color is within CONFIDENCE
+        let within = |count: u32| {
+            allocate(draws, &others, sampling, size - count).is_some_and(|(_, rest)| {
+                rest + sampling.log_likelihood(draws, colors[x], count) >= likelihood - CONFIDENCE
+            })
+        };
+        // the likelihood of the best bag for a count only falls away from the most likely
+        // count, so the bounds are bisected between it and the ends
+        let bound = |up: bool| {
+            let count = |step: u32| if up { best[x] + step } else { best[x] - step };
+            // within at near steps, not within at far steps
+            let (mut near, mut far) = (0, if up { size - best[x] } else { best[x] } as u64 + 1);
+            while far - near > 1 {
+                let middle = (near + far) / 2;
+                if within(count(middle as u32)) {
+                    near = middle;
+                } else {
+                    far = middle;
+                }
+            }
+            count(near as u32)
+        };
+        bounds.insert(colors[x].to_string(), (bound(false), bound(true)));
+    }
+    let bag = (0..colors.len())
+        .map(|x| Cubes::one(colors[x], best[x]))
+        .sum();
+    Some(Estimate { bag, bounds })
+}
+
+// queries like "sum(id) where max.red > 5 and any(draw.blue == 0)"
+//
+// query      := aggregate [where expr] | expr
+// aggregate  := count | sum(value) | product(value)
+// expr       := and {or and}
+// and        := not {and not}
+// not        := not not | (expr) | any(expr) | all(expr) | value op value
+// value      := number | id | power | draws | max.color | min.color | total.color | draw.color
+//
+// draw is the draw of the closest any or all, power is taken over every color of the games
+#[derive(Debug, PartialEq)]
+enum QueryError {
+    UnexpectedChar(char),
+    UnexpectedToken(String),
+    UnexpectedEnd,
+    DrawOutsideQuantifier,
+    Overflow,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum QueryToken {
+    Word(String),
+    Number(u64),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for QueryToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryToken::Word(word) => write!(f, "{}", word),
+            QueryToken::Number(number) => write!(f, "{}", number),
+            QueryToken::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+// longer symbols first so "<=" is not read as "<"
+const SYMBOLS: [&str; 9] = ["==", "!=", "<=", ">=", "<", ">", "(", ")", "."];
+
+fn get_query_tokens(text: &str) -> Result<Vec<QueryToken>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let number = rest[..len]
+                .parse::<u64>()
+                .map_err(|_| QueryError::UnexpectedToken(rest[..len].to_string()))?;
+            tokens.push(QueryToken::Number(number));
+            len
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(QueryToken::Word(rest[..len].to_string()));
+            len
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(*symbol))
+                .ok_or(QueryError::UnexpectedChar(c))?;
+            tokens.push(QueryToken::Symbol(symbol));
+            symbol.len()
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Stat {
+    Max,
+    Min,
+    Total,
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Number(u64),
+    Id,
+    Power,
+    Draws,
+    Stat(Stat, String),
+    Draw(String),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, PartialEq)]
+enum Expr {
+    Compare(Value, Op, Value),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Any(Box<Expr>),
+    All(Box<Expr>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Aggregate {
+    Count,
+    Sum(Value),
+    Product(Value),
+}
+
+#[derive(Debug, PartialEq)]
+struct Query {
+    aggregate: Option<Aggregate>,
+    filter: Option<Expr>,
+}
+
+// what a query gives, the ids of the games without an aggregate
+#[derive(Debug, PartialEq)]
+enum Answer {
+    Games(Vec<u32>),
+    Number(u64),
+}
+
+// recursive descent over the tokens, quantifiers counts the any and all around the position
+struct QueryParser {
+    tokens: Vec<QueryToken>,
+    pos: usize,
+    quantifiers: usize,
+}
+
+impl QueryParser {
+    fn peek(&self) -> Option<&QueryToken> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<QueryToken, QueryError> {
+        let token = self.peek().cloned().ok_or(QueryError::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn is_word(&self, word: &str) -> bool {
+        self.peek() == Some(&QueryToken::Word(word.to_string()))
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(QueryToken::Symbol(next)) if *next == symbol)
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), QueryError> {
+        match self.next()? {
+            QueryToken::Symbol(next) if next == symbol => Ok(()),
+            token => Err(QueryError::UnexpectedToken(token.to_string())),
+        }
+    }
+
+    fn query(&mut self) -> Result<Query, QueryError> {
+        let aggregate = match self.peek() {
+            Some(QueryToken::Word(word)) if word == "count" => {
+                self.pos += 1;
+                Some(Aggregate::Count)
+            }
+            Some(QueryToken::Word(word)) if word == "sum" || word == "product" => {
+                let sum = word == "sum";
+                self.pos += 1;
+                self.expect("(")?;
+                let value = self.value()?;
+                self.expect(")")?;
+                Some(match sum {
+                    true => Aggregate::Sum(value),
+                    false => Aggregate::Product(value),
+                })
+            }
+            _ => None,
+        };
+        let filter = match aggregate {
+            Some(_) if self.is_word("where") => {
+                self.pos += 1;
+                Some(self.or()?)
+            }
+            Some(_) => None,
+            None => Some(self.or()?),
+        };
+        match self.peek() {
+            Some(token) => Err(QueryError::UnexpectedToken(token.to_string())),
+            None => Ok(Query { aggregate, filter }),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.and()?;
+        while self.is_word("or") {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.not()?;
+        while self.is_word("and") {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, QueryError> {
+        if self.is_word("not") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.is_symbol("(") {
+            self.pos += 1;
+            let expr = self.or()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        for (word, quantifier) in [("any", Expr::Any as fn(_) -> _), ("all", Expr::All)] {
+            if self.is_word(word) {
+                self.pos += 1;
+                self.expect("(")?;
+                self.quantifiers += 1;
+                let expr = self.or()?;
+                self.quantifiers -= 1;
+                self.expect(")")?;
+                return Ok(quantifier(Box::new(expr)));
+            }
+        }
+        let left = self.value()?;
+        let op = match self.next()? {
+            QueryToken::Symbol("==") => Op::Eq,
+            QueryToken::Symbol("!=") => Op::Ne,
+            QueryToken::Symbol("<") => Op::Lt,
+            QueryToken::Symbol("<=") => Op::Le,
+            QueryToken::Symbol(">") => Op::Gt,
+            QueryToken::Symbol(">=") => Op::Ge,
+            token => return Err(QueryError::UnexpectedToken(token.to_string())),
+        };
+        Ok(Expr::Compare(left, op, self.value()?))
+    }
+
+    // ".red" after max, min, total and draw
+    fn color(&mut self) -> Result<String, QueryError> {
+        self.expect(".")?;
+        match self.next()? {
+            QueryToken::Word(color) => Ok(color),
+            token => Err(QueryError::UnexpectedToken(token.to_string())),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, QueryError> {
+        let word = match self.next()? {
+            QueryToken::Number(number) => return Ok(Value::Number(number)),
+            QueryToken::Word(word) => word,
+            token => return Err(QueryError::UnexpectedToken(token.to_string())),
+        };
+        match word.as_str() {
+            "id" => Ok(Value::Id),
+            "power" => Ok(Value::Power),
+            "draws" => Ok(Value::Draws),
+            "max" => Ok(Value::Stat(Stat::Max, self.color()?)),
+            "min" => Ok(Value::Stat(Stat::Min, self.color()?)),
+            "total" => Ok(Value::Stat(Stat::Total, self.color()?)),
+            "draw" if self.quantifiers == 0 => Err(QueryError::DrawOutsideQuantifier),
+            "draw" => Ok(Value::Draw(self.color()?)),
+            _ => Err(QueryError::UnexpectedToken(word)),
+        }
+    }
+}
+
+impl Value {
+    // powers and totals are taken in u64, as they need not fit into the counts
+    fn get(
+        &self,
+        game: &Game,
+        draw: Option<&Cubes>,
+        colors: &BTreeSet<&str>,
+    ) -> Result<u64, QueryError> {
+        match self {
+            Value::Number(number) => Ok(*number),
+            Value::Id => Ok(game.id as u64),
+            Value::Power => game
+                .max()
+                .power(colors.iter().copied())
+                .ok_or(QueryError::Overflow),
+            Value::Draws => Ok(game.draws.len() as u64),
+            Value::Stat(Stat::Max, color) => Ok(game.max().get(color) as u64),
+            Value::Stat(Stat::Min, color) => Ok(game.min().get(color) as u64),
+            Value::Stat(Stat::Total, color) => Ok(game.total_of(color)),
+            Value::Draw(color) => {
+                Ok(draw.expect("Should have been inside any or all").get(color) as u64)
+            }
+        }
+    }
+}
+
+impl Expr {
+    fn matches(
+        &self,
+        game: &Game,
+        draw: Option<&Cubes>,
+        colors: &BTreeSet<&str>,
+    ) -> Result<bool, QueryError> {
+        match self {
+            Expr::Compare(left, op, right) => {
+                let (left, right) = (
+                    left.get(game, draw, colors)?,
+                    right.get(game, draw, colors)?,
+                );
+                Ok(match op {
+                    Op::Eq => left == right,
+                    Op::Ne => left != right,
+                    Op::Lt => left < right,
+                    Op::Le => left <= right,
+                    Op::Gt => left > right,
+                    Op::Ge => left >= right,
+                })
+            }
+            Expr::And(left, right) => {
+                Ok(left.matches(game, draw, colors)? && right.matches(game, draw, colors)?)
+            }
+            Expr::Or(left, right) => {
+                Ok(left.matches(game, draw, colors)? || right.matches(game, draw, colors)?)
+            }
+            Expr::Not(expr) => Ok(!expr.matches(game, draw, colors)?),
+            Expr::Any(expr) => {
+                for draw in &game.draws {
+                    if expr.matches(game, Some(draw), colors)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            Expr::All(expr) => {
+                for draw in &game.draws {
+                    if !expr.matches(game, Some(draw), colors)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl Query {
+    fn parse(text: &str) -> Result<Query, QueryError> {
+        QueryParser {
+            tokens: get_query_tokens(text)?,
+            pos: 0,
+            quantifiers: 0,
+        }
+        .query()
+    }
+
+    fn run(&self, games: &[Game]) -> Result<Answer, QueryError> {
+        let colors = get_colors(games);
+        let mut found: Vec<&Game> = vec![];
+        for game in games {
+            match &self.filter {
+                Some(filter) if !filter.matches(game, None, &colors)? => (),
+                _ => found.push(game),
+            }
+        }
+        let values = |value: &Value| {
+            found
+                .iter()
+                .map(|game| value.get(game, None, &colors))
+                .collect::<Result<Vec<u64>, QueryError>>()
+        };
+        match &self.aggregate {
+            None => Ok(Answer::Games(found.iter().map(|game| game.id).collect())),
+            Some(Aggregate::Count) => Ok(Answer::Number(found.len() as u64)),
+            Some(Aggregate::Sum(value)) => values(value)?
+                .into_iter()
+                .try_fold(0u64, |a, b| a.checked_add(b))
+                .map(Answer::Number)
+                .ok_or(QueryError::Overflow),
+            Some(Aggregate::Product(value)) => values(value)?
+                .into_iter()
+                .try_fold(1u64, |a, b| a.checked_mul(b))
+                .map(Answer::Number)
+                .ok_or(QueryError::Overflow),
+        }
+    }
+}
+
+// count games with up to 6 draws of up to 20 cubes of each color
+fn random_games(count: usize, mut seed: u64) -> String {
+    let mut random = |n: u64| {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) % n
+    };
+    let mut result = String::new();
+
+    for id in 1..=count {
+        let draws = (0..1 + random(6))
+            .map(|_| {
+                let mut cubes = ["red", "green", "blue"]
+                    .iter()
+                    .filter_map(|color| {
+                        // a quarter of the colors are not drawn
+                        let (drawn, count) = (random(4) > 0, 1 + random(20));
+                        drawn.then(|| format!("{} {}", count, color))
+                    })
+                    .collect::<Vec<String>>();
+                if cubes.is_empty() {
+                    cubes.push(format!("{} red", 1 + random(20)));
+                }
+                cubes.join(", ")
+            })
+            .collect::<Vec<String>>();
+        result += &format!("Game {}: {}\n", id, draws.join("; "));
+    }
+    result
+}
+
+// parses the games with the regex and the byte parser, the time of each
+fn bench_parsers(input: &str) -> (Duration, Duration) {
+    let start = Instant::now();
+    let games = input
+        .split_terminator('\n')
+        .map(get_game_regex)
+        .collect::<Vec<Game>>();
+    let regex = start.elapsed();
+
+    let start = Instant::now();
+    let parsed = get_all_games(input);
+    let bytes = start.elapsed();
+
+    assert_eq!(games, parsed);
+    (regex, bytes)
+}
+
+// the draws of a game, and the ones that make it impossible
+fn describe_game(game: &Game, bag: &Cubes) -> String {
+    let mut result = format!("Game {}\n", game.id);
+    for (x, draw) in game.draws.iter().enumerate() {
+        result += &format!(
+            "draw {}: {}{}\n",
+            x + 1,
+            draw,
+            if draw.fits(bag) { "" } else { " (impossible)" }
+        );
+    }
+    let total = game
+        .total()
+        .map_or("too many cubes".to_string(), |total| total.to_string());
+    for (name, cubes) in [
+        ("max", game.max().to_string()),
+        ("min", game.min().to_string()),
+        ("total", total),
+    ] {
+        result += &format!("{}: {}\n", name, cubes);
+    }
+    result
+}
+
 fn main() {
     let input = fs::read_to_string("2.input").expect("Should have been able to read the file");
+    let args = env::args().collect::<Vec<String>>();
+    let bag = get_bag(&args);
+
+    // --game <id> shows the draws of a game instead of the sums
+    if let Some(id) = option(&args, "--game") {
+        let id = id.parse::<u32>().expect("--game needs a game id");
+        match get_all_games(&input).iter().find(|game| game.id == id) {
+            Some(game) => print!("{}", describe_game(game, &bag)),
+            None => println!("there is no game {}", id),
+        }
+        return;
+    }
+
+    // --estimate <id> guesses the bag of a game with --bag-size cubes (as many as the bag by
+    // default), --sampling with|without tells if a draw puts cubes back before the next one
+    if let Some(id) = option(&args, "--estimate") {
+        let id = id.parse::<u32>().expect("--estimate needs a game id");
+        let value = |name: &str| option(&args, name);
+        let size = value("--bag-size").map_or_else(
+            || {
+                bag.count()
+                    .try_into()
+                    .expect("Should have been a bag with few enough cubes, use --bag-size")
+            },
+            |size| {
+                size.parse::<u32>()
+                    .expect("--bag-size needs a number of cubes")
+            },
+        );
+        let sampling = Sampling::from_name(value("--sampling").unwrap_or("without"))
+            .expect("--sampling needs with or without");
+        let games = get_all_games(&input);
+        let game = games
+            .iter()
+            .find(|game| game.id == id)
+            .expect("Should have been a game with the id");
+        match estimate_bag(game, sampling, size) {
+            Some(estimate) => {
+                for (color, (low, high)) in &estimate.bounds {
+                    println!("{}: {} ({}..{})", color, estimate.bag.get(color), low, high);
+                }
+            }
+            None => println!("no bag of {} cubes gives the draws of game {}", size, id),
+        }
+        return;
+    }
+
+    // --query <query> shows the ids of the games that match or the aggregate of them
+    if let Some(text) = option(&args, "--query") {
+        let query = Query::parse(text).expect("Should have been a valid query");
+        match query
+            .run(&get_all_games(&input))
+            .expect("Should have been able to run the query")
+        {
+            Answer::Games(ids) => println!(
+                "games {}",
+                ids.iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Answer::Number(number) => println!("{}", number),
+        }
+        return;
+    }
+
+    // --bench [games] times both parsers on generated games, 100000 by default
+    if let Some(pos) = args.iter().position(|arg| arg == "--bench") {
+        let count = args.get(pos + 1).map_or(100000, |count| {
+            count
+                .parse::<usize>()
+                .expect("--bench needs a number of games")
+        });
+        let input = random_games(count, 2023);
+        let (regex, bytes) = bench_parsers(&input);
+        println!("{} games, {} bytes", count, input.len());
+        println!("regex: {:?}", regex);
+        println!("bytes: {:?}", bytes);
+        return;
+    }
+
+    // --minimal-bag [k] shows the bag with the fewest cubes for all games or at least k of them
+    if let Some(pos) = args.iter().position(|arg| arg == "--minimal-bag") {
+        let games = get_all_games(&input);
+        let minimal = match args.get(pos + 1) {
+            Some(k) => {
+                let k = k
+                    .parse::<usize>()
+                    .expect("--minimal-bag needs a number of games");
+                get_minimal_bag_for(&games, k)
+            }
+            None => Some(get_minimal_bag(&games)),
+        };
+        match minimal {
+            Some(minimal) => println!("{}: games {}", minimal, get_ids(&games, &minimal)),
+            None => println!("there are only {} games", games.len()),
+        }
+        return;
+    }
 
-    let result = get_valid_game_sum(&input);
+    // --tighten shows the games that become invalid as each limit of the bag is lowered
+    if args.iter().any(|arg| arg == "--tighten") {
+        let games = get_all_games(&input);
+        for color in bag.colors() {
+            for (limit, ids) in get_tightening(&games, &bag, color) {
+                println!(
+                    "{} {}: games {}",
+                    color,
+                    limit,
+                    ids.iter()
+                        .map(|id| id.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                );
+            }
+        }
+        return;
+    }
+
+    let result = get_valid_game_sum(&input, &bag);
 
     println!("valid game sum: {}", result);
 
     let result2 = get_game_power_sum(&input);
     println!("power game sum: {}", result2);
-
 }
 
 #[cfg(test)]
@@ -148,19 +1285,11 @@ mod tests {
     fn test_cubes_one() {
         assert_eq!(
             Cubes::get_cubes_one("3 blue, 4 red"),
-            Cubes {
-                red: 4,
-                green: 0,
-                blue: 3,
-            }
+            Cubes::from([("red", 4), ("green", 0), ("blue", 3)])
         );
         assert_eq!(
             Cubes::get_cubes_one("8 green, 6 blue, 20 red"),
-            Cubes {
-                red: 20,
-                green: 8,
-                blue: 6,
-            }
+            Cubes::from([("red", 20), ("green", 8), ("blue", 6)])
         );
     }
 
@@ -171,74 +1300,74 @@ mod tests {
                 "8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red"
             ),
             [
-                Cubes {
-                    red: 20,
-                    green: 8,
-                    blue: 6,
-                },
-                Cubes {
-                    red: 4,
-                    green: 13,
-                    blue: 5,
-                },
-                Cubes {
-                    red: 1,
-                    green: 5,
-                    blue: 0,
-                }
+                Cubes::from([("red", 20), ("green", 8), ("blue", 6)]),
+                Cubes::from([("red", 4), ("green", 13), ("blue", 5)]),
+                Cubes::from([("red", 1), ("green", 5), ("blue", 0)])
             ]
         );
     }
 
     #[test]
     fn test_max_cubes() {
+        let game =
+            get_game("Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red");
         assert_eq!(
-            Cubes::get_max_cubes(
-                "8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red"
-            ),
-            Cubes {
-                red: 20,
-                green: 13,
-                blue: 6,
-            }
+            game.max(),
+            Cubes::from([("red", 20), ("green", 13), ("blue", 6)])
+        );
+        assert_eq!(
+            game.min(),
+            Cubes::from([("red", 1), ("green", 5), ("blue", 0)])
         );
+        assert_eq!(
+            game.total(),
+            Some(Cubes::from([("red", 25), ("green", 26), ("blue", 11)]))
+        );
+
+        let game = get_game("Game 1: 4294967295 red; 4294967295 red");
+        assert_eq!(game.total(), None);
+        assert!(describe_game(&game, &Cubes::bag()).ends_with("total: too many cubes\n"));
     }
 
     #[test]
     fn test_game() {
+        let game = get_game("Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green");
         assert_eq!(
-            get_game("Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green"),
-            (
-                1,
-                Cubes {
-                    red: 4,
-                    green: 2,
-                    blue: 6
-                }
-            )
+            game,
+            Game {
+                id: 1,
+                draws: Cubes::get_cubes_all("3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green")
+            }
+        );
+        assert_eq!(
+            game.max(),
+            Cubes::from([("red", 4), ("green", 2), ("blue", 6)])
         );
     }
 
     #[test]
-    fn test_is_valid() {
-        assert_eq!(
-            Cubes {
-                red: 5,
-                green: 5,
-                blue: 5
-            }
-            .is_valid(),
-            true
-        );
+    fn test_invalid_draws() {
+        let input =
+            fs::read_to_string("2sample.input").expect("Should have been able to read the file");
+        let games = get_all_games(&input);
+
+        // game 3 has 20 red in the first draw, game 4 15 blue in the last one
         assert_eq!(
-            Cubes {
-                red: 13,
-                green: 5,
-                blue: 5
-            }
-            .is_valid(),
-            false
+            games
+                .iter()
+                .map(|game| game.invalid_draws(&Cubes::bag()))
+                .collect::<Vec<Vec<usize>>>(),
+            [vec![], vec![], vec![0], vec![2], vec![]]
         );
+        assert!(describe_game(&games[2], &Cubes::bag())
+            .contains("draw 1: 6 blue, 8 green, 20 red (impossible)\n"));
+        assert!(describe_game(&games[2], &Cubes::bag()).contains("draw 3: 5 green, 1 red\n"));
+    }
+
+    #[test]
+    fn test_is_valid() {
+        assert!(Cubes::from([("red", 5), ("green", 5), ("blue", 5)]).fits(&Cubes::bag()));
+        assert!(!Cubes::from([("red", 13), ("green", 5), ("blue", 5)]).fits(&Cubes::bag()));
     }
 
     #[test]
@@ -246,48 +1375,16 @@ mod tests {
         let input =
             fs::read_to_string("2sample.input").expect("Should have been able to read the file");
         assert_eq!(
-            get_all_games(&input),
+            get_all_games(&input)
+                .iter()
+                .map(|game| (game.id, game.max()))
+                .collect::<Vec<(u32, Cubes)>>(),
             [
-                (
-                    1,
-                    Cubes {
-                        red: 4,
-                        green: 2,
-                        blue: 6
-                    }
-                ),
-                (
-                    2,
-                    Cubes {
-                        red: 1,
-                        green: 3,
-                        blue: 4
-                    }
-                ),
-                (
-                    3,
-                    Cubes {
-                        red: 20,
-                        green: 13,
-                        blue: 6
-                    }
-                ),
-                (
-                    4,
-                    Cubes {
-                        red: 14,
-                        green: 3,
-                        blue: 15
-                    }
-                ),
-                (
-                    5,
-                    Cubes {
-                        red: 6,
-                        green: 3,
-                        blue: 2
-                    }
-                )
+                (1, Cubes::from([("red", 4), ("green", 2), ("blue", 6)])),
+                (2, Cubes::from([("red", 1), ("green", 3), ("blue", 4)])),
+                (3, Cubes::from([("red", 20), ("green", 13), ("blue", 6)])),
+                (4, Cubes::from([("red", 14), ("green", 3), ("blue", 15)])),
+                (5, Cubes::from([("red", 6), ("green", 3), ("blue", 2)]))
             ]
         )
     }
@@ -297,19 +1394,315 @@ mod tests {
         let input =
             fs::read_to_string("2sample.input").expect("Should have been able to read the file");
 
-        assert_eq!(get_valid_game_sum(&input), 8);
+        assert_eq!(get_valid_game_sum(&input, &Cubes::bag()), 8);
+    }
+
+    #[test]
+    fn test_bag() {
+        assert_eq!(
+            Cubes::parse_bag("red=12, green = 13,blue=14".split(',')),
+            Ok(Cubes::bag())
+        );
+        assert_eq!(
+            Cubes::parse_bag("red=12\n\nyellow=2\n".lines()),
+            Ok(Cubes::from([("red", 12), ("yellow", 2)]))
+        );
+        assert_eq!(
+            Cubes::parse_bag(["red"].into_iter()),
+            Err(BagError::InvalidLimit("red".to_string()))
+        );
+        assert_eq!(
+            Cubes::parse_bag(["=2", "red=x"].into_iter()),
+            Err(BagError::InvalidLimit("=2".to_string()))
+        );
+        assert_eq!(
+            Cubes::parse_bag("red=4294967294,red=1".split(',')),
+            Ok(Cubes::from([("red", 4294967295)]))
+        );
+        assert_eq!(
+            Cubes::parse_bag("red=4294967295,red=1".split(',')),
+            Err(BagError::InvalidLimit("red=1".to_string()))
+        );
+        assert_eq!(
+            Cubes::from([("red", 4294967295), ("blue", 4294967295)]).count(),
+            2 * 4294967295
+        );
+
+        let args = ["2", "--bag", "red=20,green=13,blue=14"].map(String::from);
+        let input =
+            fs::read_to_string("2sample.input").expect("Should have been able to read the file");
+        assert_eq!(get_valid_game_sum(&input, &get_bag(&args)), 11);
+        assert_eq!(get_bag(&[]), Cubes::bag());
+    }
+
+    #[test]
+    fn test_minimal_bag() {
+        let input =
+            fs::read_to_string("2sample.input").expect("Should have been able to read the file");
+        let games = get_all_games(&input);
+
+        assert_eq!(
+            get_minimal_bag(&games),
+            Cubes::from([("red", 20), ("green", 13), ("blue", 15)])
+        );
+        assert_eq!(
+            get_minimal_bag_for(&games, 5),
+            Some(get_minimal_bag(&games))
+        );
+        assert_eq!(get_minimal_bag_for(&games, 6), None);
+        assert_eq!(get_minimal_bag_for(&games, 0), Some(Cubes::default()));
+        // games 2 and 5
+        assert_eq!(
+            get_minimal_bag_for(&games, 2),
+            Some(Cubes::from([("red", 6), ("green", 3), ("blue", 4)]))
+        );
+        assert_eq!(
+            get_ids(
+                &games,
+                &Cubes::from([("red", 6), ("green", 3), ("blue", 4)])
+            ),
+            "2, 5"
+        );
+        // games 1, 2 and 5
+        assert_eq!(
+            get_minimal_bag_for(&games, 3).map(|bag| bag.count()),
+            Some(15)
+        );
+    }
+
+    #[test]
+    fn test_tightening() {
+        let input =
+            fs::read_to_string("2sample.input").expect("Should have been able to read the file");
+        let games = get_all_games(&input);
+
+        // games 1, 2 and 5 are valid, with at most 4, 1 and 6 red
+        assert_eq!(
+            get_tightening(&games, &Cubes::bag(), "red"),
+            [(5, vec![5]), (3, vec![1]), (0, vec![2])]
+        );
+        assert_eq!(
+            get_tightening(&games, &Cubes::bag(), "green"),
+            [(2, vec![2, 5]), (1, vec![1])]
+        );
+        assert_eq!(get_tightening(&games, &Cubes::bag(), "yellow"), []);
+    }
+
+    #[test]
+    fn test_estimate_bag() {
+        let game = get_game("Game 1: 3 red, 1 blue; 2 red, 2 blue; 1 red");
+
+        // 6 red and 3 blue drawn, 10 * 6 / 9 is 6.67
+        let estimate = estimate_bag(&game, Sampling::WithReplacement, 10).unwrap();
+        assert_eq!(estimate.bag, Cubes::from([("red", 7), ("blue", 3)]));
+        assert_eq!(estimate_bag(&game, Sampling::WithReplacement, 1), None);
+        assert_eq!(estimate_bag(&game, Sampling::WithoutReplacement, 4), None);
+        assert_eq!(
+            estimate_bag(&game, Sampling::WithoutReplacement, 5)
+                .unwrap()
+                .bounds["red"],
+            (3, 3)
+        );
+        // the bounds are searched from the estimate, not over every count
+        let estimate = estimate_bag(&game, Sampling::WithoutReplacement, 100000).unwrap();
+        let (low, high) = estimate.bounds["red"];
+        assert!(low < estimate.bag.get("red") && estimate.bag.get("red") < high);
+        // and the bag starts from the share of each color, so huge bags are quick
+        let start = Instant::now();
+        let estimate = estimate_bag(&game, Sampling::WithReplacement, 20000000).unwrap();
+        assert_eq!(estimate.bag.get("red"), 13333333);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // compare with every split of the cubes
+        for sampling in [Sampling::WithReplacement, Sampling::WithoutReplacement] {
+            for size in 5..30 {
+                let estimate = estimate_bag(&game, sampling, size).unwrap();
+                let likelihood = |red: u32| {
+                    sampling.log_likelihood(&game.draws, "red", red)
+                        + sampling.log_likelihood(&game.draws, "blue", size - red)
+                };
+                let best = (0..=size)
+                    .max_by(|&a, &b| likelihood(a).total_cmp(&likelihood(b)))
+                    .unwrap();
+                assert_eq!(estimate.bag.count(), size as u64);
+                assert!(likelihood(best) - likelihood(estimate.bag.get("red")) < 1e-9);
+
+                let (low, high) = estimate.bounds["red"];
+                assert!(low <= estimate.bag.get("red") && estimate.bag.get("red") <= high);
+                assert!(likelihood(low) >= likelihood(best) - CONFIDENCE);
+                assert!(low == 0 || likelihood(low - 1) < likelihood(best) - CONFIDENCE);
+                assert!(likelihood(high + 1) < likelihood(best) - CONFIDENCE);
+            }
+        }
+    }
+
+    #[test]
+    fn test_query() {
+        let input =
+            fs::read_to_string("2sample.input").expect("Should have been able to read the file");
+        let games = get_all_games(&input);
+        let run = |text: &str| Query::parse(text).and_then(|query| query.run(&games));
+
+        assert_eq!(
+            run("sum(id) where max.red <= 12 and max.green <= 13 and max.blue <= 14"),
+            Ok(Answer::Number(8))
+        );
+        assert_eq!(run("sum(power)"), Ok(Answer::Number(2286)));
+        assert_eq!(
+            run("max.red > 5 and any(draw.blue == 0)"),
+            Ok(Answer::Games(vec![3, 4]))
+        );
+        assert_eq!(
+            run("count where not all(draw.green > 1)"),
+            Ok(Answer::Number(3))
+        );
+        assert_eq!(
+            run("product(draws) where id < 3 or (total.red>=25)"),
+            Ok(Answer::Number(27))
+        );
+        assert_eq!(run("id == 6"), Ok(Answer::Games(vec![])));
+        assert_eq!(run("count where min.yellow != 0"), Ok(Answer::Number(0)));
+        assert_eq!(
+            run("any(all(draw.red > 0) and draw.red > 3)"),
+            Ok(Answer::Games(vec![3, 4, 5]))
+        );
+    }
+
+    #[test]
+    fn test_query_errors() {
+        assert_eq!(
+            Query::parse("draw.red > 1"),
+            Err(QueryError::DrawOutsideQuantifier)
+        );
+        assert_eq!(
+            Query::parse("sum(draw.red)"),
+            Err(QueryError::DrawOutsideQuantifier)
+        );
+        assert_eq!(Query::parse("max.red >"), Err(QueryError::UnexpectedEnd));
+        assert_eq!(Query::parse("(id > 1"), Err(QueryError::UnexpectedEnd));
+        assert_eq!(
+            Query::parse("max.red = 2"),
+            Err(QueryError::UnexpectedChar('='))
+        );
+        assert_eq!(
+            Query::parse("id > 1 id"),
+            Err(QueryError::UnexpectedToken("id".to_string()))
+        );
+        assert_eq!(
+            Query::parse("count id > 1"),
+            Err(QueryError::UnexpectedToken("id".to_string()))
+        );
+        assert_eq!(
+            Query::parse("max > 1"),
+            Err(QueryError::UnexpectedToken(">".to_string()))
+        );
+
+        let games =
+            get_all_games("Game 1: 4294967295 red\nGame 2: 4294967295 red\nGame 3: 2 red\n");
+        assert_eq!(
+            Query::parse("product(max.red) where id < 3").and_then(|query| query.run(&games)),
+            Ok(Answer::Number(4294967295 * 4294967295))
+        );
+        assert_eq!(
+            Query::parse("product(max.red)").and_then(|query| query.run(&games)),
+            Err(QueryError::Overflow)
+        );
+
+        // powers and totals beyond the counts
+        let games = get_all_games(
+            "Game 1: 70000 red, 70000 blue\nGame 2: 4294967295 red; 4294967295 red\n",
+        );
+        let run = |text: &str| Query::parse(text).and_then(|query| query.run(&games));
+        assert_eq!(
+            run("sum(power) where id == 1"),
+            Ok(Answer::Number(70000 * 70000))
+        );
+        assert_eq!(run("total.red > 4294967295"), Ok(Answer::Games(vec![2])));
+        assert_eq!(
+            run("sum(total.red)"),
+            Ok(Answer::Number(70000 + 2 * 4294967295))
+        );
+        let games = get_all_games("Game 1: 4294967295 red, 4294967295 blue, 4294967295 green\n");
+        assert_eq!(
+            Query::parse("power > 0").and_then(|query| query.run(&games)),
+            Err(QueryError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_parse_game() {
+        let line = "Game 12: 3 blue, 4 red; 1 red, 2 green, 6 blue, 0 yellow; 2 green, 1 green";
+        assert_eq!(parse_game(line), Some(get_game_regex(line)));
+        assert_eq!(
+            parse_game(line).unwrap().draws[2],
+            Cubes::from([("green", 3)])
+        );
+
+        for line in [
+            "",
+            "Game : 1 red",
+            "Game x: 1 red",
+            "Game 1: red",
+            "Game 1: 1 red;",
+            "Game 1: 1 red, ",
+            "Game 1: 1  red",
+            "Game 1: 1 red 2 blue",
+            "Game 1 1 red",
+            "game 1: 1 red",
+            "Game 4294967296: 1 red",
+            "Game 1: 4294967296 red",
+            "Game 1: 4294967295 red, 1 red",
+        ] {
+            assert_eq!(parse_game(line), None, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_bench_parsers() {
+        let input = random_games(2000, 7);
+        assert_eq!(input.lines().count(), 2000);
+        // panics if the parsers disagree
+        bench_parsers(&input);
+
+        let input = fs::read_to_string("2.input").expect("Should have been able to read the file");
+        bench_parsers(&input);
     }
 
     #[test]
     fn test_power() {
         assert_eq!(
-            Cubes {
-                red: 4,
-                green: 2,
-                blue: 6
-            }.power(),
-            48
+            Cubes::from([("red", 4), ("green", 2), ("blue", 6)]).power(["red", "green", "blue"]),
+            Some(48)
+        );
+    }
+
+    #[test]
+    fn test_colors() {
+        let cubes = Cubes::get_cubes_one("2 yellow, 1 red, 3 yellow");
+        assert_eq!(cubes, Cubes::from([("red", 1), ("yellow", 5)]));
+        assert_eq!(cubes.to_string(), "1 red, 5 yellow");
+        assert!(!cubes.fits(&Cubes::bag()));
+        assert!(Cubes::from([("red", 12), ("yellow", 0)]).fits(&Cubes::bag()));
+
+        let other = Cubes::from([("red", 4), ("blue", 2)]);
+        assert_eq!(
+            cubes.max(&other),
+            Cubes::from([("red", 4), ("blue", 2), ("yellow", 5)])
+        );
+        assert_eq!(cubes.min(&other), Cubes::from([("red", 1)]));
+        assert_eq!(
+            cubes + other,
+            Cubes::from([("red", 5), ("blue", 2), ("yellow", 5)])
+        );
+
+        let games = get_all_games("Game 1: 2 yellow, 1 red\nGame 2: 3 blue\n");
+        assert_eq!(
+            get_colors(&games).into_iter().collect::<Vec<&str>>(),
+            ["blue", "red", "yellow"]
         );
+        assert_eq!(games[0].max().power(["red", "yellow"]), Some(2));
+        // game 2 has no red or yellow cubes
+        assert_eq!(games[1].max().power(get_colors(&games)), Some(0));
     }
 
     #[test]
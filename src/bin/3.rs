@@ -1,46 +1,283 @@
+use aoc::args::option;
+use aoc::grid::{Grid, GridError, Neighborhood, Pos, Region};
+use std::collections::{HashMap, VecDeque};
+use std::env;
 use std::fs;
+use std::io::{BufRead, BufReader};
+use std::ops::Range;
+
+// type of the numbers and of the sums, wide enough for long numbers
+type Number = u64;
+
+#[derive(Debug, PartialEq)]
+enum SchemaError {
+    // digits at x, y that do not make a Number, e.g. too many of them
+    InvalidNumber { x: usize, y: usize, text: String },
+    // ratio of the gear at x, y does not fit into a Number
+    RatioOverflow { x: usize, y: usize },
+    // a sum does not fit into a Number
+    SumOverflow,
+    // x, y is outside of the schema
+    OutOfRange { x: usize, y: usize },
+    // row y could not be read
+    Unreadable { y: usize, message: String },
+    // row y has a different length than the first one
+    RaggedRow { y: usize, len: usize, width: usize },
+}
+
+impl SchemaError {
+    // same error for a schema that starts dy rows further up
+    fn moved_down(self, dy: usize) -> Self {
+        match self {
+            SchemaError::InvalidNumber { x, y, text } => {
+                SchemaError::InvalidNumber { x, y: y + dy, text }
+            }
+            SchemaError::RatioOverflow { x, y } => SchemaError::RatioOverflow { x, y: y + dy },
+            SchemaError::SumOverflow => SchemaError::SumOverflow,
+            SchemaError::OutOfRange { x, y } => SchemaError::OutOfRange { x, y: y + dy },
+            SchemaError::Unreadable { y, message } => {
+                SchemaError::Unreadable { y: y + dy, message }
+            }
+            SchemaError::RaggedRow { y, len, width } => SchemaError::RaggedRow {
+                y: y + dy,
+                len,
+                width,
+            },
+        }
+    }
+}
+
+fn checked_sum(values: impl IntoIterator<Item = Number>) -> Result<Number, SchemaError> {
+    values.into_iter().try_fold(0, |sum: Number, value| {
+        sum.checked_add(value).ok_or(SchemaError::SumOverflow)
+    })
+}
+
+// which chars are digits
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Digits {
+    // only 0 to 9
+    Ascii,
+    // also the decimal digits of the scripts in UNICODE_ZEROS
+    Unicode,
+}
+
+// first char of each block of ten decimal digits, e.g. arabic-indic, devanagari, thai, fullwidth
+const UNICODE_ZEROS: [char; 20] = [
+    '0', '\u{660}', '\u{6f0}', '\u{7c0}', '\u{966}', '\u{9e6}', '\u{a66}', '\u{ae6}', '\u{b66}',
+    '\u{be6}', '\u{c66}', '\u{ce6}', '\u{d66}', '\u{e50}', '\u{ed0}', '\u{f20}', '\u{1040}',
+    '\u{17e0}', '\u{1810}', '\u{ff10}',
+];
+
+impl Digits {
+    // value of a digit, None if it is none
+    fn value(&self, value: char) -> Option<u32> {
+        match self {
+            Digits::Ascii => value.to_digit(10),
+            Digits::Unicode => UNICODE_ZEROS.iter().find_map(|&zero| {
+                (value as u32)
+                    .checked_sub(zero as u32)
+                    .filter(|&digit| digit < 10)
+            }),
+        }
+    }
+}
+
+// how many numbers must be next to a gear
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum PartCount {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+impl PartCount {
+    fn matches(&self, count: usize) -> bool {
+        match *self {
+            PartCount::Exactly(n) => count == n,
+            PartCount::AtLeast(n) => count >= n,
+        }
+    }
+}
+
+// how the numbers next to a gear make up its ratio
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Combine {
+    Product,
+    Sum,
+    Max,
+}
+
+impl Combine {
+    // None if the result does not fit into a Number
+    fn apply(&self, values: &[Number]) -> Option<Number> {
+        match self {
+            Combine::Product => values
+                .iter()
+                .try_fold(1, |product: Number, &value| product.checked_mul(value)),
+            Combine::Sum => values
+                .iter()
+                .try_fold(0, |sum: Number, &value| sum.checked_add(value)),
+            Combine::Max => Some(values.iter().copied().max().unwrap_or(0)),
+        }
+    }
+}
+
+// meaning of the chars of a schema, the default is the one of the puzzle
+#[derive(Debug, PartialEq, Clone)]
+struct Dialect {
+    // neither number nor symbol, also used outside of the schema
+    blank: char,
+    digits: Digits,
+    // None: everything that is not a digit or blank
+    symbols: Option<Vec<char>>,
+    gears: Vec<char>,
+    parts: PartCount,
+    combine: Combine,
+    // cells next to a number, and numbers next to a gear
+    neighborhood: Neighborhood,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Dialect {
+            blank: '.',
+            digits: Digits::Ascii,
+            symbols: None,
+            gears: vec!['*'],
+            parts: PartCount::Exactly(2),
+            combine: Combine::Product,
+            neighborhood: Neighborhood::Moore(1),
+        }
+    }
+}
+
+impl Dialect {
+    fn is_numeric(&self, value: char) -> bool {
+        self.digits.value(value).is_some()
+    }
+
+    fn is_symbol(&self, value: char) -> bool {
+        match &self.symbols {
+            Some(symbols) => symbols.contains(&value),
+            None => !self.is_numeric(value) && value != self.blank,
+        }
+    }
+
+    fn is_gear(&self, value: char) -> bool {
+        self.gears.contains(&value)
+    }
+
+    // ratio of a gear with these numbers next to it, None if it is not a real gear
+    fn ratio(
+        &self,
+        gear: &ThingPosition,
+        serials: &[Number],
+    ) -> Result<Option<Number>, SchemaError> {
+        if !self.parts.matches(serials.len()) {
+            return Ok(None);
+        }
+        match self.combine.apply(serials) {
+            Some(ratio) => Ok(Some(ratio)),
+            None => Err(SchemaError::RatioOverflow {
+                x: gear.x,
+                y: gear.y,
+            }),
+        }
+    }
+
+    // --blank <char>, --digits ascii|unicode, --symbols <chars>, --gears <chars>, --parts <n> or <n>+,
+    // --combine product|sum|max and
+    // --neighborhood orthogonal|moore|moore:<radius>|vonneumann:<radius>|mask:<file>
+    fn from_args(args: &[String]) -> Self {
+        let mut dialect = Dialect::default();
+        let value = |name: &str| option(args, name);
+
+        if let Some(blank) = value("--blank") {
+            dialect.blank = blank.chars().next().expect("--blank needs a char");
+        }
+        if let Some(digits) = value("--digits") {
+            dialect.digits = match digits {
+                "ascii" => Digits::Ascii,
+                "unicode" => Digits::Unicode,
+                _ => panic!("unknown digits {}", digits),
+            };
+        }
+        if let Some(symbols) = value("--symbols") {
+            dialect.symbols = Some(symbols.chars().collect());
+        }
+        if let Some(gears) = value("--gears") {
+            dialect.gears = gears.chars().collect();
+        }
+        if let Some(parts) = value("--parts") {
+            dialect.parts = match parts.strip_suffix('+') {
+                Some(n) => PartCount::AtLeast(n.parse().expect("--parts needs a number")),
+                None => PartCount::Exactly(parts.parse().expect("--parts needs a number")),
+            };
+        }
+        if let Some(combine) = value("--combine") {
+            dialect.combine = match combine {
+                "product" => Combine::Product,
+                "sum" => Combine::Sum,
+                "max" => Combine::Max,
+                _ => panic!("unknown combine {}", combine),
+            };
+        }
+        if let Some(neighborhood) = value("--neighborhood") {
+            let radius = |r: &str| r.parse::<usize>().expect("radius needs a number");
+            dialect.neighborhood = match neighborhood.split_once(':') {
+                None if neighborhood == "orthogonal" => Neighborhood::VonNeumann(1),
+                None if neighborhood == "moore" => Neighborhood::Moore(1),
+                Some(("moore", r)) => Neighborhood::Moore(radius(r)),
+                Some(("vonneumann", r)) => Neighborhood::VonNeumann(radius(r)),
+                Some(("mask", file)) => Neighborhood::from_mask(
+                    &fs::read_to_string(file).expect("Should have been able to read the mask"),
+                )
+                .expect("Should have been a mask with one @"),
+                _ => panic!("unknown neighborhood {}", neighborhood),
+            };
+        }
+        // the blank also fills the outside, it can't be anything else
+        let blank = dialect.blank;
+        if dialect.is_numeric(blank) || dialect.is_gear(blank) || dialect.is_symbol(blank) {
+            panic!("blank {} is also a digit, gear or symbol", blank);
+        }
+        dialect
+    }
+}
 
 #[derive(Debug, PartialEq)]
 struct Schema {
-    field: Vec<Vec<char>>,
+    field: Grid<char>,
+    dialect: Dialect,
 }
 
 impl Schema {
     fn new(value: &str) -> Self {
         Schema {
-            field: value
-                .split_terminator('\n')
-                .map(|line| -> Vec<char> {
-                    let mut result: Vec<char> = Vec::new();
-                    result.extend(line.chars());
-                    result
-                })
-                .collect::<Vec<Vec<char>>>(),
+            field: Grid::parse(value).expect("Should have been a rectangular schema"),
+            dialect: Dialect::default(),
         }
     }
 
+    fn with_dialect(self, dialect: Dialect) -> Self {
+        Schema { dialect, ..self }
+    }
+
     fn len_x(&self) -> usize {
-        if self.len_y() == 0 {
-            0
-        } else {
-            self.field[0].len()
-        }
+        self.field.width()
     }
 
     fn len_y(&self) -> usize {
-        self.field.len()
+        self.field.height()
     }
 
-    fn get(&self, x: i32, y: i32) -> char {
-        if x >= 0 && y >= 0 && (y as usize) < self.len_y() && (x as usize) < self.len_x() {
-            self.field[y as usize][x as usize]
-        } else {
-            '.' // is not a number nor a symbol
-        }
+    fn get(&self, x: isize, y: isize) -> char {
+        // outside is not a number nor a symbol
+        self.field.get_or(x, y, self.dialect.blank)
     }
 
     // find all numbers that might be a serial
-    fn find_serial_candidates(&self) -> ThingIterator {
+    fn find_serial_candidates(&self) -> ThingIterator<'_> {
         ThingIterator {
             schema: self,
             pos: ThingPosition {
@@ -48,41 +285,96 @@ impl Schema {
                 y: 0,
                 length: 0,
             },
-            is_thing: is_numeric,
+            is_thing: Dialect::is_numeric,
         }
     }
 
-    // find all adjacent chars of a number
-    fn find_adjacent<'a>(&'a self, pos: &'a ThingPosition) -> AdjacentIterator {
-        AdjacentIterator {
-            schema: self,
-            pos: pos,
-            x: -2,
-            y: -2,
+    // things with a cell within the columns xs and rows ys, row by row, including their cells
+    // outside of the columns
+    fn find_things_within(
+        &self,
+        is_thing: fn(&Dialect, char) -> bool,
+        xs: Range<isize>,
+        ys: Range<isize>,
+    ) -> Vec<ThingPosition> {
+        let (len_x, len_y) = (self.len_x() as isize, self.len_y() as isize);
+        let is_thing = |x: isize, y: isize| x < len_x && is_thing(&self.dialect, self.get(x, y));
+        let mut result = vec![];
+
+        for y in ys.start.max(0)..ys.end.min(len_y) {
+            let mut x = xs.start.max(0);
+            // the first thing might start before the columns
+            while x > 0 && is_thing(x - 1, y) {
+                x -= 1;
+            }
+            while x < xs.end.min(len_x) {
+                if is_thing(x, y) {
+                    let start = x;
+                    while is_thing(x, y) {
+                        x += 1;
+                    }
+                    result.push(ThingPosition {
+                        x: start as usize,
+                        y: y as usize,
+                        length: (x - start) as usize,
+                    });
+                } else {
+                    x += 1;
+                }
+            }
         }
+        result
+    }
+
+    // find all adjacent chars of a number, row by row
+    fn find_adjacent(&self, pos: &ThingPosition) -> impl Iterator<Item = char> + '_ {
+        let (x, y, length) = (pos.x as isize, pos.y as isize, pos.length as isize);
+        let mut cells = self
+            .dialect
+            .neighborhood
+            .offsets()
+            .into_iter()
+            .flat_map(|(dx, dy)| (x..x + length).map(move |x| (y + dy, x + dx)))
+            .filter(|&(cell_y, cell_x)| cell_y != y || cell_x < x || cell_x >= x + length)
+            .collect::<Vec<(isize, isize)>>();
+        cells.sort();
+        cells.dedup();
+
+        cells.into_iter().map(|(y, x)| self.get(x, y))
     }
 
     // check if really serial
     fn is_serial(&self, pos: &ThingPosition) -> bool {
-        self.find_adjacent(pos).any(|x| is_symbol(x))
+        self.find_adjacent(pos).any(|x| self.dialect.is_symbol(x))
     }
 
-    fn get_number(&self, pos: &ThingPosition) -> u32 {
-        self.field[pos.y][pos.x..pos.x + pos.length]
-            .into_iter()
-            .collect::<String>()
-            .parse::<u32>()
-            .unwrap()
+    fn get_number(&self, pos: &ThingPosition) -> Result<Number, SchemaError> {
+        let digits = &self.field.row(pos.y)[pos.x..pos.x + pos.length];
+
+        digits
+            .iter()
+            .try_fold(0, |number: Number, &digit| {
+                number
+                    .checked_mul(10)?
+                    .checked_add(self.dialect.digits.value(digit)?.into())
+            })
+            .ok_or_else(|| SchemaError::InvalidNumber {
+                x: pos.x,
+                y: pos.y,
+                text: digits.iter().collect(),
+            })
     }
 
-    fn sum_serial(&self) -> u32 {
-        self.find_serial_candidates()
-            .filter(|x| self.is_serial(&x))
-            .map(|x| self.get_number(&x))
-            .sum()
+    fn sum_serial(&self) -> Result<Number, SchemaError> {
+        checked_sum(
+            self.find_serial_candidates()
+                .filter(|x| self.is_serial(x))
+                .map(|x| self.get_number(&x))
+                .collect::<Result<Vec<Number>, SchemaError>>()?,
+        )
     }
 
-    fn find_gear_candidates(&self) -> ThingIterator {
+    fn find_gear_candidates(&self) -> ThingIterator<'_> {
         ThingIterator {
             schema: self,
             pos: ThingPosition {
@@ -90,22 +382,455 @@ impl Schema {
                 y: 0,
                 length: 0,
             },
-            is_thing: is_gear,
+            is_thing: Dialect::is_gear,
         }
     }
 
-    fn sum_gears(&self) -> u32 {
-        self
-            .find_gear_candidates()
-            .map(|gear| {
-                self.find_serial_candidates()
-                    .filter(|serial| gear.is_adjacent(serial)) // find all numbers next to gear
-                    .map(|serial| self.get_number(&serial))
-                    .collect::<Vec<u32>>()
-            })
-            .filter(|serials| serials.len() == 2) // must be 2 numbers
-            .map(|serials| serials.iter().fold(1, |a, b| a * b)) // multiply them
-            .sum::<u32>() // add products
+    // position and ratio of every real gear
+    fn gear_ratios(&self) -> Result<Vec<(ThingPosition, Number)>, SchemaError> {
+        self.gear_ratios_of(self.find_gear_candidates())
+    }
+
+    // position and ratio of the real gears among the candidates
+    fn gear_ratios_of(
+        &self,
+        candidates: impl Iterator<Item = ThingPosition>,
+    ) -> Result<Vec<(ThingPosition, Number)>, SchemaError> {
+        let index = NumberIndex::new(self);
+        let mut result = vec![];
+
+        for gear in candidates {
+            let serials = index
+                .find_adjacent(&gear, &self.dialect.neighborhood) // find all numbers next to gear
+                .map(|serial| self.get_number(serial))
+                .collect::<Result<Vec<Number>, SchemaError>>()?;
+            // e.g. 2 numbers multiplied
+            if let Some(ratio) = self.dialect.ratio(&gear, &serials)? {
+                result.push((gear, ratio));
+            }
+        }
+        Ok(result)
+    }
+
+    // ratio of a single gear, only looking at the numbers within reach of it
+    fn ratio_of(&self, gear: &ThingPosition) -> Result<Option<Number>, SchemaError> {
+        let reach = self.dialect.neighborhood.reach() as isize;
+        let (x, y, length) = (gear.x as isize, gear.y as isize, gear.length as isize);
+        let serials = self
+            .find_things_within(
+                Dialect::is_numeric,
+                x - reach..x + length + reach,
+                y - reach..y + reach + 1,
+            )
+            .iter()
+            .filter(|serial| gear.is_adjacent(serial, &self.dialect.neighborhood))
+            .map(|serial| self.get_number(serial))
+            .collect::<Result<Vec<Number>, SchemaError>>()?;
+        self.dialect.ratio(gear, &serials)
+    }
+
+    fn sum_gears(&self) -> Result<Number, SchemaError> {
+        checked_sum(self.gear_ratios()?.into_iter().map(|(_, ratio)| ratio))
+    }
+
+    // groups of numbers and symbols connected through the neighborhood
+    fn regions(&self) -> (Grid<Option<usize>>, Vec<Region>) {
+        self.field
+            .label_regions(&self.dialect.neighborhood, |&x| x != self.dialect.blank)
+    }
+
+    fn annotate(&self) -> Result<Grid<CellKind>, SchemaError> {
+        let mut kinds = self.field.map(|&x| {
+            if self.dialect.is_symbol(x) {
+                CellKind::Symbol
+            } else {
+                CellKind::Blank
+            }
+        });
+
+        for number in self.find_serial_candidates() {
+            let kind = if self.is_serial(&number) {
+                CellKind::Part
+            } else {
+                CellKind::NonPart
+            };
+            for x in number.x..number.x + number.length {
+                kinds.set(Pos::new(x, number.y), kind);
+            }
+        }
+        for (gear, _) in self.gear_ratios()? {
+            kinds.set(Pos::new(gear.x, gear.y), CellKind::Gear);
+        }
+        Ok(kinds)
+    }
+
+    // colored schema with the gear ratios at the end of each row
+    fn render_ansi(&self) -> Result<String, SchemaError> {
+        let kinds = self.annotate()?;
+        let gears = self.gear_ratios()?;
+        let mut result = CellKind::ALL
+            .iter()
+            .map(|kind| format!("\x1b[{}m{}\x1b[0m", kind.ansi_color(), kind.name()))
+            .collect::<Vec<String>>()
+            .join(" ")
+            + "\n";
+
+        for y in 0..self.len_y() {
+            let mut color = None;
+            for (&x, &kind) in self.field.row(y).iter().zip(kinds.row(y)) {
+                // only switch colors when the kind changes
+                if color != Some(kind) {
+                    result += &format!("\x1b[{}m", kind.ansi_color());
+                    color = Some(kind);
+                }
+                result.push(x);
+            }
+            result += "\x1b[0m";
+
+            for (gear, ratio) in gears.iter().filter(|(gear, _)| gear.y == y) {
+                result += &format!("  gear {}: {}", gear.x, ratio);
+            }
+            result += "\n";
+        }
+        Ok(result)
+    }
+
+    // standalone html page with the gear ratios as tooltips and in a table
+    fn render_html(&self) -> Result<String, SchemaError> {
+        let kinds = self.annotate()?;
+        let gears = self.gear_ratios()?;
+        let mut result = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>schema</title>\n<style>\n",
+        );
+        for kind in CellKind::ALL {
+            result += &format!(".{} {{ {} }}\n", kind.name(), kind.css());
+        }
+        result += "</style>\n</head>\n<body>\n<p>";
+        result += &CellKind::ALL
+            .iter()
+            .map(|kind| format!("<span class=\"{}\">{}</span>", kind.name(), kind.name()))
+            .collect::<Vec<String>>()
+            .join(" ");
+        result += "</p>\n<pre>\n";
+
+        for y in 0..self.len_y() {
+            let cells = self
+                .field
+                .row(y)
+                .iter()
+                .zip(kinds.row(y))
+                .enumerate()
+                .map(|(x, (&value, &kind))| (x, value, kind))
+                .collect::<Vec<(usize, char, CellKind)>>();
+
+            // one span per run of the same kind, but one per gear for its ratio
+            for run in cells.chunk_by(|a, b| a.2 == b.2 && a.2 != CellKind::Gear) {
+                let (x, _, kind) = run[0];
+                let title = match gears.iter().find(|(gear, _)| gear.x == x && gear.y == y) {
+                    Some((_, ratio)) => format!(" title=\"ratio {}\"", ratio),
+                    None => String::new(),
+                };
+                result += &format!(
+                    "<span class=\"{}\"{}>{}</span>",
+                    kind.name(),
+                    title,
+                    run.iter()
+                        .map(|&(_, value, _)| html_escape(value))
+                        .collect::<String>()
+                );
+            }
+            result += "\n";
+        }
+
+        result += "</pre>\n<table>\n<tr><th>x</th><th>y</th><th>ratio</th></tr>\n";
+        for (gear, ratio) in &gears {
+            result += &format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                gear.x, gear.y, ratio
+            );
+        }
+        Ok(result + "</table>\n</body>\n</html>\n")
+    }
+}
+
+// a schema that is edited cell by cell, only looking at the numbers and gears around the edited
+// cell to keep the sums up to date
+struct EditableSchema {
+    schema: Schema,
+    // value of each serial and ratio of each real gear, by their start
+    serials: HashMap<(usize, usize), Number>,
+    gears: HashMap<(usize, usize), Number>,
+    serial_sum: Number,
+    gear_sum: Number,
+}
+
+// start of a thing and what it adds to a sum
+type Contribution = ((usize, usize), Number);
+
+// sum after replacing the contributions of the old things by the new contributions
+fn replace_contributions(
+    sum: Number,
+    contributions: &HashMap<(usize, usize), Number>,
+    old: &[ThingPosition],
+    new: &[Contribution],
+) -> Result<Number, SchemaError> {
+    let removed = old
+        .iter()
+        .filter_map(|thing| contributions.get(&(thing.x, thing.y)))
+        .sum::<Number>();
+    checked_sum(
+        std::iter::once(sum - removed).chain(new.iter().map(|&(_, contribution)| contribution)),
+    )
+}
+
+impl EditableSchema {
+    fn new(schema: Schema) -> Result<Self, SchemaError> {
+        let serials = schema
+            .find_serial_candidates()
+            .filter(|x| schema.is_serial(x))
+            .map(|x| Ok(((x.x, x.y), schema.get_number(&x)?)))
+            .collect::<Result<HashMap<(usize, usize), Number>, SchemaError>>()?;
+        let gears = schema
+            .gear_ratios()?
+            .into_iter()
+            .map(|(gear, ratio)| ((gear.x, gear.y), ratio))
+            .collect::<HashMap<(usize, usize), Number>>();
+
+        Ok(EditableSchema {
+            serial_sum: checked_sum(serials.values().copied())?,
+            gear_sum: checked_sum(gears.values().copied())?,
+            schema,
+            serials,
+            gears,
+        })
+    }
+
+    fn sum_serial(&self) -> Number {
+        self.serial_sum
+    }
+
+    fn sum_gears(&self) -> Number {
+        self.gear_sum
+    }
+
+    // on an error the cell and the sums stay as they were
+    fn set(&mut self, x: usize, y: usize, value: char) -> Result<(), SchemaError> {
+        let pos = Pos::new(x, y);
+        if !self.schema.field.contains(pos) {
+            return Err(SchemaError::OutOfRange { x, y });
+        }
+        // numbers within reach of the cell might change, at least the ones right next to it as
+        // they might be split or joined
+        let reach = self.schema.dialect.neighborhood.reach().max(1) as isize;
+        let (x, y) = (x as isize, y as isize);
+        let (xs, ys) = (x - reach..x + reach + 1, y - reach..y + reach + 1);
+        let old_numbers =
+            self.schema
+                .find_things_within(Dialect::is_numeric, xs.clone(), ys.clone());
+
+        // the numbers after the edit only cover the cell and cells of the numbers before, so
+        // all gears next to a changed number are within reach of those
+        let (left, right) = old_numbers
+            .iter()
+            .fold((x, x + 1), |(left, right), number| {
+                (
+                    left.min(number.x as isize),
+                    right.max((number.x + number.length) as isize),
+                )
+            });
+        let (gear_xs, gear_ys) = (
+            left - reach..right + reach,
+            y - 2 * reach..y + 2 * reach + 1,
+        );
+        let old_gears =
+            self.schema
+                .find_things_within(Dialect::is_gear, gear_xs.clone(), gear_ys.clone());
+
+        let previous = self.schema.field[pos];
+        self.schema.field.set(pos, value);
+
+        let schema = &self.schema;
+        let changes = (|| -> Result<_, SchemaError> {
+            let mut serials: Vec<Contribution> = vec![];
+            for number in schema.find_things_within(Dialect::is_numeric, xs, ys) {
+                if schema.is_serial(&number) {
+                    serials.push(((number.x, number.y), schema.get_number(&number)?));
+                }
+            }
+            let mut gears: Vec<Contribution> = vec![];
+            for gear in schema.find_things_within(Dialect::is_gear, gear_xs, gear_ys) {
+                if let Some(ratio) = schema.ratio_of(&gear)? {
+                    gears.push(((gear.x, gear.y), ratio));
+                }
+            }
+            let serial_sum =
+                replace_contributions(self.serial_sum, &self.serials, &old_numbers, &serials)?;
+            let gear_sum = replace_contributions(self.gear_sum, &self.gears, &old_gears, &gears)?;
+            Ok((serials, gears, serial_sum, gear_sum))
+        })();
+        let (serials, gears, serial_sum, gear_sum) = match changes {
+            Ok(changes) => changes,
+            Err(error) => {
+                self.schema.field.set(pos, previous);
+                return Err(error);
+            }
+        };
+
+        for number in old_numbers {
+            self.serials.remove(&(number.x, number.y));
+        }
+        self.serials.extend(serials);
+        for gear in old_gears {
+            self.gears.remove(&(gear.x, gear.y));
+        }
+        self.gears.extend(gears);
+        (self.serial_sum, self.gear_sum) = (serial_sum, gear_sum);
+        Ok(())
+    }
+}
+
+// serial and gear sums of a schema read row by row, only keeping the rows within reach of the
+// neighborhood around the current row in memory
+fn sum_stream(reader: impl BufRead, dialect: Dialect) -> Result<(Number, Number), SchemaError> {
+    let reach = dialect.neighborhood.reach();
+    let mut window: VecDeque<Vec<char>> = VecDeque::new();
+    // y of the first row in window and of the next row to sum up
+    let (mut first, mut next) = (0, 0);
+    let (mut serials, mut gears) = (0, 0);
+    let mut schema = Schema {
+        field: Grid::new(0, 0, dialect.blank),
+        dialect,
+    };
+
+    // width of the first row, all others must have the same
+    let mut width = None;
+
+    let rows = reader.lines().map(Some);
+    // None marks the end, the last rows don't get any more rows below them
+    for (y, line) in rows.chain(std::iter::once(None)).enumerate() {
+        let end = line.is_none();
+        let row = match line {
+            Some(Ok(line)) => Some(line.chars().collect::<Vec<char>>()),
+            Some(Err(error)) => {
+                return Err(SchemaError::Unreadable {
+                    y,
+                    message: error.to_string(),
+                })
+            }
+            None => None,
+        };
+        if let Some(row) = &row {
+            let width = *width.get_or_insert(row.len());
+            if row.len() != width {
+                return Err(SchemaError::RaggedRow {
+                    y,
+                    len: row.len(),
+                    width,
+                });
+            }
+        }
+        window.extend(row);
+        debug_assert!(window.len() <= 2 * reach + 1);
+
+        while next < first + window.len() && (end || next + reach < first + window.len()) {
+            let center = next - first;
+            schema.field = Grid::from_rows(window.iter().cloned().collect()).map_err(|error| {
+                match error {
+                    GridError::RaggedRow { y, len, width } => {
+                        SchemaError::RaggedRow { y, len, width }
+                    }
+                    GridError::MaskCenter(_) => unreachable!("Should not have been a mask"),
+                }
+                .moved_down(first)
+            })?;
+
+            // errors are reported with the y of the whole schema
+            let row_serials = schema
+                .find_serial_candidates()
+                .filter(|x| x.y == center && schema.is_serial(x))
+                .map(|x| schema.get_number(&x))
+                .collect::<Result<Vec<Number>, SchemaError>>()
+                .map_err(|error| error.moved_down(first))?;
+            let row_gears = schema
+                .gear_ratios_of(
+                    schema
+                        .find_gear_candidates()
+                        .filter(|gear| gear.y == center),
+                )
+                .map_err(|error| error.moved_down(first))?;
+            serials = checked_sum(std::iter::once(serials).chain(row_serials))?;
+            gears = checked_sum(
+                std::iter::once(gears).chain(row_gears.into_iter().map(|(_, ratio)| ratio)),
+            )?;
+
+            next += 1;
+            // rows above are only needed while within reach
+            while first + reach < next {
+                window.pop_front();
+                first += 1;
+            }
+        }
+    }
+    Ok((serials, gears))
+}
+
+// what a cell of the schema is part of
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum CellKind {
+    Blank,
+    Part,
+    NonPart,
+    Symbol,
+    Gear,
+}
+
+impl CellKind {
+    const ALL: [CellKind; 5] = [
+        CellKind::Blank,
+        CellKind::Part,
+        CellKind::NonPart,
+        CellKind::Symbol,
+        CellKind::Gear,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            CellKind::Blank => "blank",
+            CellKind::Part => "part",
+            CellKind::NonPart => "nonpart",
+            CellKind::Symbol => "symbol",
+            CellKind::Gear => "gear",
+        }
+    }
+
+    fn ansi_color(&self) -> &'static str {
+        match self {
+            CellKind::Blank => "2",
+            CellKind::Part => "32",
+            CellKind::NonPart => "31",
+            CellKind::Symbol => "33",
+            CellKind::Gear => "1;35",
+        }
+    }
+
+    fn css(&self) -> &'static str {
+        match self {
+            CellKind::Blank => "color: #999;",
+            CellKind::Part => "color: green;",
+            CellKind::NonPart => "color: red;",
+            CellKind::Symbol => "color: olive;",
+            CellKind::Gear => "color: purple; font-weight: bold;",
+        }
+    }
+}
+
+fn html_escape(value: char) -> String {
+    match value {
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        '&' => "&amp;".to_string(),
+        '"' => "&quot;".to_string(),
+        _ => value.to_string(),
     }
 }
 
@@ -117,30 +842,74 @@ struct ThingPosition {
 }
 
 impl ThingPosition {
-    fn is_adjacent(&self, other: &Self) -> bool {
-        self.x <= other.x + other.length
-            && self.x + self.length >= other.x
-            && self.y <= other.y + 1
-            && self.y + 1 >= other.y
+    // some cell of self is a neighbor of a cell of other
+    fn is_adjacent(&self, other: &Self, neighborhood: &Neighborhood) -> bool {
+        let (x, y, length) = (self.x as isize, self.y as isize, self.length as isize);
+
+        neighborhood.offsets().into_iter().any(|(dx, dy)| {
+            // other moved by the offset overlaps self
+            let other_x = other.x as isize + dx;
+            other.y as isize + dy == y
+                && other_x < x + length
+                && other_x + other.length as isize > x
+        })
     }
 }
 
-struct ThingIterator<'a> {
-    schema: &'a Schema,
-    pos: ThingPosition,
-    is_thing: fn(char) -> bool,
+// all numbers of a schema and the cells they cover
+struct NumberIndex {
+    numbers: Vec<ThingPosition>,
+    // index into numbers for each cell covered by a number
+    cells: Grid<Option<usize>>,
 }
 
-fn is_numeric(value: char) -> bool {
-    value.is_numeric()
-}
+impl NumberIndex {
+    fn new(schema: &Schema) -> Self {
+        let numbers = schema
+            .find_serial_candidates()
+            .collect::<Vec<ThingPosition>>();
+        let mut cells = Grid::new(schema.len_x(), schema.len_y(), None);
+
+        for (index, number) in numbers.iter().enumerate() {
+            for x in number.x..number.x + number.length {
+                cells.set(Pos::new(x, number.y), Some(index));
+            }
+        }
+
+        NumberIndex { numbers, cells }
+    }
+
+    // numbers for which `thing.is_adjacent(number)` holds, only looking at the cells around thing
+    fn find_adjacent<'a>(
+        &'a self,
+        thing: &ThingPosition,
+        neighborhood: &'a Neighborhood,
+    ) -> impl Iterator<Item = &'a ThingPosition> {
+        let reach = neighborhood.reach();
+        let mut found: Vec<usize> = vec![];
 
-fn is_symbol(value: char) -> bool {
-    !value.is_numeric() && value != '.'
+        for y in thing.y.saturating_sub(reach)..=thing.y + reach {
+            for x in thing.x.saturating_sub(reach)..thing.x + thing.length + reach {
+                if let Some(&Some(index)) = self.cells.get(Pos::new(x, y)) {
+                    if !found.contains(&index) {
+                        found.push(index);
+                    }
+                }
+            }
+        }
+
+        let thing = *thing;
+        found
+            .into_iter()
+            .map(|index| &self.numbers[index])
+            .filter(move |number| thing.is_adjacent(number, neighborhood))
+    }
 }
 
-fn is_gear(value: char) -> bool {
-    value == '*'
+struct ThingIterator<'a> {
+    schema: &'a Schema,
+    pos: ThingPosition,
+    is_thing: fn(&Dialect, char) -> bool,
 }
 
 impl Iterator for ThingIterator<'_> {
@@ -148,79 +917,124 @@ impl Iterator for ThingIterator<'_> {
 
     // Find start position and length of each serial number
     fn next(&mut self) -> Option<Self::Item> {
+        let dialect = &self.schema.dialect;
+        // the blank outside of the schema may be a thing too, so stay within the row
+        let is_thing = |x: usize, y: usize| {
+            x < self.schema.len_x()
+                && (self.is_thing)(dialect, self.schema.get(x as isize, y as isize))
+        };
         loop {
-            if (self.is_thing)(self.schema.get(self.pos.x as i32, self.pos.y as i32)) {
+            if self.pos.x >= self.schema.len_x() {
+                self.pos.x = 0;
+                self.pos.y += 1;
+            }
+            if self.pos.y >= self.schema.len_y() {
+                return None;
+            }
+            if is_thing(self.pos.x, self.pos.y) {
                 // found start of a number
-                loop {
-                    self.pos.length += 1;
-                    if !(self.is_thing)(
-                        self.schema
-                            .get((self.pos.x + self.pos.length) as i32, self.pos.y as i32),
-                    ) {
-                        break;
-                    }
+                let mut length = 1;
+                while is_thing(self.pos.x + length, self.pos.y) {
+                    length += 1;
                 }
 
-                let result = self.pos;
-                self.pos.x += self.pos.length;
-                self.pos.length = 0;
+                let result = ThingPosition { length, ..self.pos };
+                self.pos.x += length;
 
                 return Some(result);
             } else {
                 self.pos.x += 1;
-                if self.pos.x >= self.schema.len_x() {
-                    self.pos.x = 0;
-                    self.pos.y += 1;
-                }
-                if self.pos.y >= self.schema.len_y() {
-                    return None;
-                }
             }
         }
     }
 }
 
-struct AdjacentIterator<'a> {
-    schema: &'a Schema,
-    pos: &'a ThingPosition,
-    x: i32,
-    y: i32,
-}
+fn main() {
+    let args = env::args().collect::<Vec<String>>();
 
-impl Iterator for AdjacentIterator<'_> {
-    type Item = char;
+    // --stream sums up without loading the whole schema
+    if args.iter().any(|arg| arg == "--stream") {
+        let file = fs::File::open("3.input").expect("Should have been able to open the file");
+        let (serials, gears) = sum_stream(BufReader::new(file), Dialect::from_args(&args))
+            .expect("Should have been a valid schema");
+        println!("Sum of serials: {}", serials);
+        println!("Sum of gears: {}", gears);
+        return;
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.x == -2 {
-            self.x = self.pos.x as i32 - 1;
-            self.y = self.pos.y as i32 - 1;
+    let input = fs::read_to_string("3.input").expect("Should have been able to read the file");
+    let s = Schema::new(&input).with_dialect(Dialect::from_args(&args));
 
-            return Some(self.schema.get(self.x, self.y));
+    // --regions lists the connected groups of numbers and symbols instead of the sums
+    if args.iter().any(|arg| arg == "--regions") {
+        for region in s.regions().1 {
+            println!(
+                "Region {}: {} cells from ({}, {}) to ({}, {})",
+                region.id, region.size, region.min.x, region.min.y, region.max.x, region.max.y
+            );
         }
-        self.x += 1;
-        if self.x > (self.pos.x + self.pos.length) as i32 {
-            self.x = self.pos.x as i32 - 1;
-            self.y += 1;
-        }
-        if self.y == self.pos.y as i32
-            && self.x >= self.pos.x as i32
-            && self.x <= (self.pos.x + self.pos.length - 1) as i32
-        {
-            self.x = (self.pos.x + self.pos.length) as i32;
-        }
-        if self.y > (self.pos.y + 1) as i32 {
-            return None;
+        return;
+    }
+
+    // --render ansi|html shows the schema instead of the sums
+    if let Some(format) = option(&args, "--render") {
+        match format {
+            "ansi" => print!(
+                "{}",
+                s.render_ansi().expect("Should have been a valid schema")
+            ),
+            "html" => print!(
+                "{}",
+                s.render_html().expect("Should have been a valid schema")
+            ),
+            _ => panic!("--render needs ansi or html"),
         }
+        return;
+    }
 
-        Some(self.schema.get(self.x, self.y))
+    // --set <x>,<y>,<char> edits a cell and shows the sums after it, may be given several times
+    let edits = args
+        .iter()
+        .enumerate()
+        .filter(|(_, arg)| *arg == "--set")
+        .map(|(pos, _)| args.get(pos + 1).expect("--set needs <x>,<y>,<char>"))
+        .collect::<Vec<&String>>();
+    if !edits.is_empty() {
+        let mut s = EditableSchema::new(s).expect("Should have been a valid schema");
+        for edit in edits {
+            let parts = edit.splitn(3, ',').collect::<Vec<&str>>();
+            let [x, y, value] = parts[..] else {
+                panic!("--set needs <x>,<y>,<char>")
+            };
+            let (x, y) = (
+                x.parse().expect("x needs a number"),
+                y.parse().expect("y needs a number"),
+            );
+            // a rejected edit leaves the schema as it was
+            if let Err(error) = s.set(x, y, value.chars().next().expect("--set needs a char")) {
+                println!("Cannot set ({}, {}) to {}: {:?}", x, y, value, error);
+                continue;
+            }
+            println!(
+                "After setting ({}, {}) to {}: serials {}, gears {}",
+                x,
+                y,
+                value,
+                s.sum_serial(),
+                s.sum_gears()
+            );
+        }
+        return;
     }
-}
 
-fn main() {
-    let input = fs::read_to_string("3.input").expect("Should have been able to read the file");
-    let s = Schema::new(&input);
-    println!("Sum of serials: {}", s.sum_serial());
-    println!("Sum of gears: {}", s.sum_gears());
+    println!(
+        "Sum of serials: {}",
+        s.sum_serial().expect("Should have been a valid schema")
+    );
+    println!(
+        "Sum of gears: {}",
+        s.sum_gears().expect("Should have been a valid schema")
+    );
 }
 
 #[cfg(test)]
@@ -232,10 +1046,12 @@ mod tests {
         assert_eq!(
             Schema::new("467..114..\n...*......\n"),
             Schema {
-                field: vec![
+                field: Grid::from_rows(vec![
                     vec!['4', '6', '7', '.', '.', '1', '1', '4', '.', '.'],
                     vec!['.', '.', '.', '*', '.', '.', '.', '.', '.', '.'],
-                ]
+                ])
+                .unwrap(),
+                dialect: Dialect::default(),
             }
         )
     }
@@ -335,7 +1151,7 @@ mod tests {
             panic!("broken text")
         };
 
-        assert_eq!(s.is_serial(&serial), true);
+        assert!(s.is_serial(&serial));
     }
 
     #[test]
@@ -345,7 +1161,7 @@ mod tests {
             panic!("broken text")
         };
 
-        assert_eq!(s.get_number(&serial), 467);
+        assert_eq!(s.get_number(&serial), Ok(467));
     }
 
     #[test]
@@ -354,7 +1170,7 @@ mod tests {
             fs::read_to_string("3sample.input").expect("Should have been able to read the file");
         let s = Schema::new(&input);
 
-        assert_eq!(s.sum_serial(), 4361);
+        assert_eq!(s.sum_serial(), Ok(4361));
     }
 
     #[test]
@@ -400,54 +1216,554 @@ mod tests {
             length: 3,
         };
 
-        assert_eq!(
-            num.is_adjacent(&ThingPosition {
+        assert!(num.is_adjacent(
+            &ThingPosition {
                 x: 5,
                 y: 2,
                 length: 1
-            }),
-            true
-        );
-        assert_eq!(
-            num.is_adjacent(&ThingPosition {
+            },
+            &Neighborhood::Moore(1)
+        ));
+        assert!(!num.is_adjacent(
+            &ThingPosition {
                 x: 4,
                 y: 2,
                 length: 1
-            }),
-            false
-        );
-        assert_eq!(
-            num.is_adjacent(&ThingPosition {
+            },
+            &Neighborhood::Moore(1)
+        ));
+        assert!(num.is_adjacent(
+            &ThingPosition {
                 x: 5,
                 y: 1,
                 length: 1
-            }),
-            true
-        );
-        assert_eq!(
-            num.is_adjacent(&ThingPosition {
+            },
+            &Neighborhood::Moore(1)
+        ));
+        assert!(num.is_adjacent(
+            &ThingPosition {
                 x: 9,
                 y: 1,
                 length: 1
-            }),
-            true
-        );
-        assert_eq!(
-            num.is_adjacent(&ThingPosition {
+            },
+            &Neighborhood::Moore(1)
+        ));
+        assert!(!num.is_adjacent(
+            &ThingPosition {
                 x: 10,
                 y: 1,
                 length: 1
+            },
+            &Neighborhood::Moore(1)
+        ));
+    }
+
+    #[test]
+    fn test_sum_gears() {
+        let input =
+            fs::read_to_string("3sample.input").expect("Should have been able to read the file");
+        let s = Schema::new(&input);
+
+        assert_eq!(s.sum_gears(), Ok(467835));
+    }
+
+    // schema of random digits, symbols and gears
+    fn random_schema(width: usize, height: usize, mut seed: u64) -> String {
+        let chars = [
+            '.', '.', '.', '.', '1', '2', '3', '4', '5', '6', '7', '8', '9', '*', '#', '+',
+        ];
+        let mut result = String::new();
+
+        for _ in 0..height {
+            for _ in 0..width {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                // half of the cells are blank, to keep the numbers short
+                if seed >> 63 == 1 {
+                    result.push('.');
+                } else {
+                    result.push(chars[(seed >> 59) as usize & 15]);
+                }
+            }
+            result.push('\n');
+        }
+        result
+    }
+
+    #[test]
+    fn test_number_index() {
+        let s = Schema::new(&random_schema(60, 40, 3));
+        let index = NumberIndex::new(&s);
+
+        let neighborhoods = [
+            Neighborhood::Moore(1),
+            Neighborhood::VonNeumann(2),
+            Neighborhood::Custom(vec![(0, -3), (2, 1)]),
+        ];
+
+        for neighborhood in &neighborhoods {
+            for gear in s.find_gear_candidates() {
+                let mut indexed = index
+                    .find_adjacent(&gear, neighborhood)
+                    .copied()
+                    .collect::<Vec<ThingPosition>>();
+                indexed.sort_by_key(|pos| (pos.y, pos.x));
+                let scanned = s
+                    .find_serial_candidates()
+                    .filter(|serial| gear.is_adjacent(serial, neighborhood))
+                    .collect::<Vec<ThingPosition>>();
+
+                assert_eq!(indexed, scanned);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dialect() {
+        let dialect = Dialect::default();
+
+        assert!(dialect.is_numeric('4'));
+        assert!(dialect.is_symbol('#'));
+        assert!(!dialect.is_symbol('.'));
+        assert!(!dialect.is_symbol('4'));
+        assert!(dialect.is_gear('*'));
+        assert!(!dialect.is_gear('#'));
+
+        let dialect = Dialect {
+            symbols: Some(vec!['#']),
+            ..Default::default()
+        };
+        assert!(dialect.is_symbol('#'));
+        assert!(!dialect.is_symbol('*'));
+    }
+
+    #[test]
+    fn test_digits() {
+        assert_eq!(Digits::Ascii.value('7'), Some(7));
+        assert_eq!(Digits::Ascii.value('\u{663}'), None);
+        assert_eq!(Digits::Unicode.value('\u{663}'), Some(3));
+        assert_eq!(Digits::Unicode.value('\u{ff19}'), Some(9));
+        // numeric, but no decimal digit
+        assert_eq!(Digits::Unicode.value('²'), None);
+
+        let input = "..²..\n.5*7.\n.....\n\u{663}4...\n";
+        let ascii = Schema::new(input);
+        let unicode = Schema::new(input).with_dialect(Dialect {
+            digits: Digits::Unicode,
+            ..Default::default()
+        });
+        // ² and the arabic-indic 3 are symbols unless they are digits
+        assert_eq!(ascii.sum_serial(), Ok(5 + 7 + 4));
+        assert_eq!(unicode.sum_serial(), Ok(5 + 7));
+        assert_eq!(unicode.sum_gears(), Ok(5 * 7));
+        assert_eq!(
+            Schema::new("\u{663}4*\n")
+                .with_dialect(Dialect {
+                    digits: Digits::Unicode,
+                    ..Default::default()
+                })
+                .sum_serial(),
+            Ok(34)
+        );
+    }
+
+    #[test]
+    fn test_schema_errors() {
+        let input = ".".repeat(21) + "\n99999999999999999999*\n";
+        let s = Schema::new(&input);
+        assert_eq!(
+            s.sum_serial(),
+            Err(SchemaError::InvalidNumber {
+                x: 0,
+                y: 1,
+                text: "99999999999999999999".to_string()
+            })
+        );
+        assert_eq!(
+            sum_stream(input.as_bytes(), Dialect::default()),
+            s.sum_serial().map(|serial| (serial, 0))
+        );
+
+        let s = Schema::new("9999999999*9999999999\n");
+        assert_eq!(s.sum_serial(), Ok(19999999998));
+        assert_eq!(
+            s.sum_gears(),
+            Err(SchemaError::RatioOverflow { x: 10, y: 0 })
+        );
+
+        // the edit is rejected and nothing changes
+        let mut s = EditableSchema::new(Schema::new(".999999999*9999999999\n")).unwrap();
+        let sums = (s.sum_serial(), s.sum_gears());
+        assert_eq!(
+            s.set(0, 0, '9'),
+            Err(SchemaError::RatioOverflow { x: 10, y: 0 })
+        );
+        assert_eq!((s.sum_serial(), s.sum_gears()), sums);
+        assert_eq!(s.schema.get(0, 0), '.');
+    }
+
+    #[test]
+    fn test_dialect_from_args() {
+        let args = ["3", "--gears", "*$", "--parts", "1+", "--combine", "max"].map(String::from);
+
+        assert_eq!(
+            Dialect::from_args(&args),
+            Dialect {
+                gears: vec!['*', '$'],
+                parts: PartCount::AtLeast(1),
+                combine: Combine::Max,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "blank . is also a digit, gear or symbol")]
+    fn test_dialect_from_args_blank_gear() {
+        Dialect::from_args(&["3", "--gears", "."].map(String::from));
+    }
+
+    #[test]
+    #[should_panic(expected = "blank 5 is also a digit, gear or symbol")]
+    fn test_dialect_from_args_blank_digit() {
+        Dialect::from_args(&["3", "--blank", "5"].map(String::from));
+    }
+
+    #[test]
+    fn test_things_blank_thing() {
+        // a blank that is a gear must not make the scan run past the row
+        let schema = Schema::new("..\n.*").with_dialect(Dialect {
+            gears: vec!['.', '*'],
+            ..Default::default()
+        });
+
+        assert_eq!(
+            schema.find_gear_candidates().collect::<Vec<_>>(),
+            vec![
+                ThingPosition {
+                    x: 0,
+                    y: 0,
+                    length: 2
+                },
+                ThingPosition {
+                    x: 0,
+                    y: 1,
+                    length: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sum_gears_dialect() {
+        let input =
+            fs::read_to_string("3sample.input").expect("Should have been able to read the file");
+        let sum = |dialect: Dialect| {
+            Schema::new(&input)
+                .with_dialect(dialect)
+                .sum_gears()
+                .unwrap()
+        };
+
+        // 467 * 35 + 755 * 598
+        assert_eq!(sum(Dialect::default()), 467835);
+        // 467 + 35 + 617 + 755 + 598
+        assert_eq!(
+            sum(Dialect {
+                parts: PartCount::AtLeast(1),
+                combine: Combine::Sum,
+                ..Default::default()
+            }),
+            2472
+        );
+        // only 617 is alone next to a gear
+        assert_eq!(
+            sum(Dialect {
+                parts: PartCount::Exactly(1),
+                ..Default::default()
             }),
-            false
+            617
+        );
+        // the $ only touches 664, so it has too few numbers
+        assert_eq!(
+            sum(Dialect {
+                gears: vec!['*', '$'],
+                combine: Combine::Max,
+                ..Default::default()
+            }),
+            467 + 755
         );
     }
 
     #[test]
-    fn test_sum_gears() {
+    fn test_sum_serial_dialect() {
+        let input =
+            fs::read_to_string("3sample.input").expect("Should have been able to read the file");
+        let s = Schema::new(&input).with_dialect(Dialect {
+            symbols: Some(vec!['#']),
+            ..Default::default()
+        });
+
+        // 633 is the only number next to a #
+        assert_eq!(s.sum_serial(), Ok(633));
+    }
+
+    #[test]
+    fn test_gear_ratios() {
         let input =
             fs::read_to_string("3sample.input").expect("Should have been able to read the file");
         let s = Schema::new(&input);
 
-        assert_eq!(s.sum_gears(), 467835);
+        assert_eq!(
+            s.gear_ratios().unwrap(),
+            [
+                (
+                    ThingPosition {
+                        x: 3,
+                        y: 1,
+                        length: 1
+                    },
+                    16345
+                ),
+                (
+                    ThingPosition {
+                        x: 5,
+                        y: 8,
+                        length: 1
+                    },
+                    451490
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn test_annotate() {
+        let s = Schema::new("467...1\n...*...\n12....<\n");
+        let kinds = s.annotate().unwrap();
+
+        assert_eq!(kinds[Pos::new(0, 0)], CellKind::Part);
+        assert_eq!(kinds[Pos::new(6, 0)], CellKind::NonPart);
+        assert_eq!(kinds[Pos::new(3, 1)], CellKind::Symbol);
+        assert_eq!(kinds[Pos::new(6, 2)], CellKind::Symbol);
+        assert_eq!(kinds[Pos::new(0, 2)], CellKind::NonPart);
+        assert_eq!(kinds[Pos::new(3, 0)], CellKind::Blank);
+
+        let s = Schema::new("467.114\n...*...\n");
+        assert_eq!(s.annotate().unwrap()[Pos::new(3, 1)], CellKind::Gear);
+    }
+
+    #[test]
+    fn test_render() {
+        let s = Schema::new("4.1\n*..\n2<.\n");
+
+        assert_eq!(
+            s.render_ansi()
+                .unwrap()
+                .lines()
+                .skip(1)
+                .collect::<Vec<&str>>(),
+            [
+                "\x1b[32m4\x1b[2m.\x1b[31m1\x1b[0m",
+                "\x1b[1;35m*\x1b[2m..\x1b[0m  gear 0: 8",
+                "\x1b[32m2\x1b[33m<\x1b[2m.\x1b[0m",
+            ]
+        );
+
+        let html = s.render_html().unwrap();
+        assert!(html.contains("<span class=\"gear\" title=\"ratio 8\">*</span>"));
+        assert!(html.contains("<span class=\"symbol\">&lt;</span>"));
+        assert!(html.contains("<span class=\"blank\">..</span>"));
+        assert!(html.contains("<tr><td>0</td><td>1</td><td>8</td></tr>"));
+    }
+
+    #[test]
+    fn test_neighborhoods() {
+        let input =
+            fs::read_to_string("3sample.input").expect("Should have been able to read the file");
+        let s = |neighborhood: Neighborhood| {
+            Schema::new(&input).with_dialect(Dialect {
+                neighborhood,
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(s(Neighborhood::Moore(1)).sum_serial(), Ok(4361));
+        // 467, 592 and 755 only touch a symbol diagonally
+        assert_eq!(
+            s(Neighborhood::VonNeumann(1)).sum_serial(),
+            Ok(4361 - 467 - 592 - 755)
+        );
+        // every number is within two cells of a symbol
+        assert_eq!(s(Neighborhood::Moore(2)).sum_serial(), Ok(4361 + 114 + 58));
+        // no * has two numbers right next to it, but two have them diagonally
+        assert_eq!(s(Neighborhood::VonNeumann(1)).sum_gears(), Ok(0));
+        assert_eq!(
+            s(Neighborhood::from_mask("#.#\n.@.\n#.#\n").unwrap()).sum_gears(),
+            Ok(467 * 35 + 755 * 598)
+        );
+    }
+
+    #[test]
+    fn test_regions() {
+        let input =
+            fs::read_to_string("3sample.input").expect("Should have been able to read the file");
+        let (labels, regions) = Schema::new(&input).regions();
+
+        assert_eq!(
+            regions
+                .iter()
+                .map(|region| region.size)
+                .collect::<Vec<usize>>(),
+            [6, 3, 4, 4, 4, 2, 7, 4]
+        );
+        // 755, the gear below it and 598
+        assert_eq!(
+            regions[6],
+            Region {
+                id: 6,
+                size: 7,
+                min: Pos::new(5, 7),
+                max: Pos::new(8, 9)
+            }
+        );
+        assert_eq!(labels[Pos::new(3, 1)], Some(0));
+        assert_eq!(labels[Pos::new(3, 0)], None);
+
+        // 467 and 35 only touch the gear diagonally
+        let s = Schema::new(&input).with_dialect(Dialect {
+            neighborhood: Neighborhood::VonNeumann(1),
+            ..Default::default()
+        });
+        assert_eq!(s.regions().1[0].size, 3);
+    }
+
+    #[test]
+    fn test_editable_schema() {
+        let input =
+            fs::read_to_string("3sample.input").expect("Should have been able to read the file");
+        let mut s = EditableSchema::new(Schema::new(&input)).unwrap();
+        assert_eq!((s.sum_serial(), s.sum_gears()), (4361, 467835));
+
+        // 467 and 35 only touch the first gear
+        s.set(3, 1, '.').unwrap();
+        assert_eq!(
+            (s.sum_serial(), s.sum_gears()),
+            (4361 - 467 - 35, 467835 - 467 * 35)
+        );
+        // 4672 instead of 467
+        s.set(3, 1, '*').unwrap();
+        s.set(3, 0, '2').unwrap();
+        assert_eq!(
+            (s.sum_serial(), s.sum_gears()),
+            (4361 - 467 + 4672, 467835 - 467 * 35 + 4672 * 35)
+        );
+
+        // the sample is 10 by 10
+        let before = s.schema.field.clone();
+        assert_eq!(
+            s.set(10, 0, '1'),
+            Err(SchemaError::OutOfRange { x: 10, y: 0 })
+        );
+        assert_eq!(
+            s.set(0, 10, '1'),
+            Err(SchemaError::OutOfRange { x: 0, y: 10 })
+        );
+        assert_eq!(s.schema.field, before);
+        assert_eq!(
+            (s.sum_serial(), s.sum_gears()),
+            (4361 - 467 + 4672, 467835 - 467 * 35 + 4672 * 35)
+        );
+    }
+
+    #[test]
+    fn test_editable_schema_random() {
+        let chars = ['.', '.', '.', '.', '.', '.', '1', '5', '9', '*', '*', '#'];
+        let (width, height) = (30, 20);
+
+        for (seed, neighborhood) in [
+            Neighborhood::Moore(1),
+            Neighborhood::VonNeumann(2),
+            Neighborhood::Custom(vec![(0, -2), (1, 1)]),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let dialect = Dialect {
+                parts: PartCount::AtLeast(1),
+                combine: Combine::Sum,
+                neighborhood,
+                ..Default::default()
+            };
+            let mut s = EditableSchema::new(
+                Schema::new(&random_schema(width, height, seed as u64)).with_dialect(dialect),
+            )
+            .unwrap();
+            let mut seed = seed as u64;
+
+            for _ in 0..300 {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let (x, y) = (
+                    (seed >> 33) as usize % width,
+                    (seed >> 43) as usize % height,
+                );
+                s.set(x, y, chars[(seed >> 53) as usize % chars.len()])
+                    .unwrap();
+
+                assert_eq!(
+                    (Ok(s.sum_serial()), Ok(s.sum_gears())),
+                    (s.schema.sum_serial(), s.schema.sum_gears())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_sum_stream() {
+        let input =
+            fs::read_to_string("3sample.input").expect("Should have been able to read the file");
+        assert_eq!(
+            sum_stream(input.as_bytes(), Dialect::default()),
+            Ok((4361, 467835))
+        );
+        assert_eq!(sum_stream("".as_bytes(), Dialect::default()), Ok((0, 0)));
+        assert_eq!(
+            sum_stream("..\n...\n..".as_bytes(), Dialect::default()),
+            Err(SchemaError::RaggedRow {
+                y: 1,
+                len: 3,
+                width: 2
+            })
+        );
+        assert_eq!(
+            sum_stream(&b"..\n.\xff\n.."[..], Dialect::default()),
+            Err(SchemaError::Unreadable {
+                y: 1,
+                message: "stream did not contain valid UTF-8".to_string()
+            })
+        );
+
+        let input = random_schema(50, 70, 7);
+        for neighborhood in [
+            Neighborhood::Moore(1),
+            Neighborhood::VonNeumann(3),
+            Neighborhood::Custom(vec![(0, -2), (1, 1)]),
+        ] {
+            let dialect = Dialect {
+                parts: PartCount::AtLeast(1),
+                combine: Combine::Sum,
+                neighborhood,
+                ..Default::default()
+            };
+            let s = Schema::new(&input).with_dialect(dialect.clone());
+
+            assert_eq!(
+                sum_stream(input.as_bytes(), dialect),
+                Ok((s.sum_serial().unwrap(), s.sum_gears().unwrap()))
+            );
+        }
     }
 }
@@ -1,101 +1,484 @@
+use aoc::args::option;
 use regex::Regex;
+use std::collections::HashMap;
+use std::env;
 use std::fs;
+use std::num::NonZeroU64;
+use std::sync::OnceLock;
 
 #[derive(Debug, PartialEq)]
+enum CardsError {
+    // line is not a card
+    InvalidLine(String),
+    // number does not fit into the card model
+    InvalidNumber(String),
+    // two cards share the same card number
+    DuplicateCard(usize),
+    // the card numbers are not exactly 1..=number of cards
+    MissingCard(usize),
+    // card wins copies of cards past the end of the table
+    PastEnd(usize),
+    // points or copies of this card do not fit into an u64
+    Overflow(usize),
+}
+
+// what to do with copies of cards past the end of the table
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum PastEnd {
+    // drop them, there is nothing to copy
+    Ignore,
+    // treat them as broken input
+    Reject,
+}
+
+// how cards are scored, the default methods are the rules of the puzzle
+trait ScoringRule {
+    fn name(&self) -> String;
+
+    // points of a card with `wins` matching numbers, None on overflow
+    fn points(&self, wins: usize) -> Option<u64> {
+        match wins {
+            0 => Some(0),
+            _ => u32::try_from(wins - 1)
+                .ok()
+                .and_then(|exp| 2_u64.checked_pow(exp)),
+        }
+    }
+
+    // copies won by the `count` instances of the card at `index` (by card number, starting
+    // at 0) in a table of `len` cards, as (index, copies) pairs; the index may be past the end
+    fn copies(&self, index: usize, wins: usize, count: u64, _len: usize) -> Vec<(usize, u64)> {
+        (index + 1..=index + wins).map(|x| (x, count)).collect()
+    }
+}
+
+// rules of the puzzle
+struct Standard;
+
+impl ScoringRule for Standard {
+    fn name(&self) -> String {
+        "standard".to_string()
+    }
+}
+
+// one point per matching number
+struct Linear;
+
+impl ScoringRule for Linear {
+    fn name(&self) -> String {
+        "linear".to_string()
+    }
+
+    fn points(&self, wins: usize) -> Option<u64> {
+        u64::try_from(wins).ok()
+    }
+}
+
+// each following card gets `divisor` times less copies than the previous one
+struct Decay {
+    divisor: NonZeroU64,
+}
+
+impl ScoringRule for Decay {
+    fn name(&self) -> String {
+        format!("decay/{}", self.divisor)
+    }
+
+    fn copies(&self, index: usize, wins: usize, count: u64, _len: usize) -> Vec<(usize, u64)> {
+        (1..=wins)
+            .map_while(|distance| {
+                let divisor = u32::try_from(distance - 1)
+                    .ok()
+                    .and_then(|exp| self.divisor.checked_pow(exp))?;
+                match count / divisor {
+                    0 => None,
+                    copies => Some((index + distance, copies)),
+                }
+            })
+            .collect()
+    }
+}
+
+// copies past the end of the table continue at the first card; cards that already handed
+// out their copies keep the extra copies, but don't win anything with them
+struct Wrap;
+
+impl ScoringRule for Wrap {
+    fn name(&self) -> String {
+        "wrap".to_string()
+    }
+
+    fn copies(&self, index: usize, wins: usize, count: u64, len: usize) -> Vec<(usize, u64)> {
+        (index + 1..=index + wins)
+            .map(|x| (x % len, count))
+            .collect()
+    }
+}
+
+fn rules() -> Vec<Box<dyn ScoringRule>> {
+    vec![
+        Box::new(Standard),
+        Box::new(Linear),
+        Box::new(Decay {
+            divisor: NonZeroU64::new(2).unwrap(),
+        }),
+        Box::new(Wrap),
+    ]
+}
+
+// numbers up to this one are kept in a bitset, at most 17 words per card
+const MAX_BITSET_NUMBER: u64 = 1024;
+
+// set of winning numbers, one bit per number
+#[derive(Debug, PartialEq, Clone)]
+enum NumberSet {
+    Bits(Vec<u64>),
+    // fallback for numbers too large for a bitset
+    Sorted(Vec<u64>),
+}
+
+impl NumberSet {
+    fn new(numbers: &[u64]) -> Self {
+        let max = numbers.iter().copied().max().unwrap_or(0);
+
+        if max > MAX_BITSET_NUMBER {
+            let mut numbers = numbers.to_vec();
+            numbers.sort_unstable();
+            numbers.dedup();
+            return NumberSet::Sorted(numbers);
+        }
+
+        let mut bits = vec![0_u64; max as usize / 64 + 1];
+        for &number in numbers {
+            bits[number as usize / 64] |= 1 << (number % 64);
+        }
+        NumberSet::Bits(bits)
+    }
+
+    fn contains(&self, number: u64) -> bool {
+        match self {
+            NumberSet::Bits(bits) => usize::try_from(number / 64)
+                .ok()
+                .and_then(|word| bits.get(word))
+                .is_some_and(|word| word & (1 << (number % 64)) != 0),
+            NumberSet::Sorted(numbers) => numbers.binary_search(&number).is_ok(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 struct Card {
-    cardnum: u8,
-    win: Vec<u8>,
-    got: Vec<u8>,
-    count: u32,
+    cardnum: usize,
+    win: NumberSet,
+    // number of got numbers in win, known once the card is parsed
+    wins: usize,
+    count: u64,
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, CardsError> {
+    value
+        .parse::<T>()
+        .map_err(|_| CardsError::InvalidNumber(value.to_string()))
+}
+
+fn parse_numbers(value: &str) -> Result<Vec<u64>, CardsError> {
+    value
+        .split(' ')
+        .filter(|x| !x.is_empty())
+        .map(parse_number::<u64>)
+        .collect()
 }
 
 impl Card {
-    fn new(value: &str) -> Self {
-        let re = Regex::new(r"^Card +([0-9]+): +(.*) +\| +(.*) *$").unwrap();
-        let caps = re.captures(value).unwrap();
-
-        Card {
-            cardnum: caps[1].parse::<u8>().unwrap(),
-            win: caps[2]
-                .split(' ')
-                .filter(|x| !x.is_empty())
-                .map(|x| x.trim().parse::<u8>().unwrap())
-                .collect::<Vec<u8>>(),
-            got: caps[3]
-                .split(' ')
-                .filter(|x| !x.is_empty())
-                .map(|x| x.parse::<u8>().unwrap())
-                .collect::<Vec<u8>>(),
+    fn new(value: &str) -> Result<Self, CardsError> {
+        static RE: OnceLock<Regex> = OnceLock::new();
+        let re = RE.get_or_init(|| Regex::new(r"^Card +([0-9]+): +(.*) +\| +(.*) *$").unwrap());
+        let caps = re
+            .captures(value)
+            .ok_or_else(|| CardsError::InvalidLine(value.to_string()))?;
+
+        let win = NumberSet::new(&parse_numbers(&caps[2])?);
+        let got = parse_numbers(&caps[3])?;
+        let wins = got.iter().filter(|&&x| win.contains(x)).count();
+
+        Ok(Card {
+            cardnum: parse_number::<usize>(&caps[1])?,
+            win,
+            wins,
             count: 1,
-        }
+        })
     }
 
-    fn wins(&self) -> u32 {
-        self.got
-            .iter()
-            .filter(|x| self.win.contains(x))
-            .count()
-            .try_into()
-            .unwrap()
+    fn wins(&self) -> usize {
+        self.wins
     }
 
-    fn points(&self) -> u32 {
-        let wins: u32 = self.wins();
+    fn points(&self, rule: &dyn ScoringRule) -> Result<u64, CardsError> {
+        rule.points(self.wins())
+            .ok_or(CardsError::Overflow(self.cardnum))
+    }
+}
 
-        match wins {
-            0 => 0,
-            _ => 2_u32.pow(wins - 1),
+// `copies` copies of card `to` were won by the instances of card `from`
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Contribution {
+    from: usize,
+    to: usize,
+    copies: u64,
+}
+
+// record of the copy cascade, in the order the copies were handed out
+#[derive(Debug, PartialEq, Default)]
+struct Trace {
+    contributions: Vec<Contribution>,
+}
+
+impl Trace {
+    fn contributions_to(&self, cardnum: usize) -> impl Iterator<Item = &Contribution> {
+        self.contributions.iter().filter(move |c| c.to == cardnum)
+    }
+
+    // example: "card 5 has 14 copies: 1 original, 1 from card 1, 4 from card 3, 8 from card 4"
+    fn explain(&self, cards: &Cards, cardnum: usize) -> Option<String> {
+        let card = cards.card(cardnum)?;
+        let reasons = std::iter::once("1 original".to_string())
+            .chain(
+                self.contributions_to(cardnum)
+                    .map(|c| format!("{} from card {}", c.copies, c.from)),
+            )
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        Some(format!(
+            "card {} has {} copies: {}",
+            cardnum, card.count, reasons
+        ))
+    }
+
+    fn to_dot(&self, cards: &Cards) -> String {
+        let mut result = String::from("digraph cascade {\n");
+        for card in cards.by_cardnum() {
+            result += &format!(
+                "    card{} [label=\"card {}\\n{} copies\"];\n",
+                card.cardnum, card.cardnum, card.count
+            );
         }
+        for c in &self.contributions {
+            result += &format!(
+                "    card{} -> card{} [label=\"{}\"];\n",
+                c.from, c.to, c.copies
+            );
+        }
+        result + "}\n"
+    }
+
+    fn to_json(&self, cards: &Cards) -> String {
+        let cards = cards
+            .by_cardnum()
+            .map(|card| format!("{{\"card\":{},\"count\":{}}}", card.cardnum, card.count))
+            .collect::<Vec<String>>()
+            .join(",");
+        let contributions = self
+            .contributions
+            .iter()
+            .map(|c| {
+                format!(
+                    "{{\"from\":{},\"to\":{},\"copies\":{}}}",
+                    c.from, c.to, c.copies
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+
+        format!(
+            "{{\"cards\":[{}],\"contributions\":[{}]}}\n",
+            cards, contributions
+        )
     }
 }
 
+#[derive(Clone)]
 struct Cards {
     cards: Vec<Card>,
 }
 
 impl Cards {
-    fn new(value: &str) -> Cards {
-        Cards {
+    fn new(value: &str) -> Result<Cards, CardsError> {
+        Ok(Cards {
             cards: value
                 .split_terminator('\n')
-                .map(|line| Card::new(line))
-                .collect(),
-        }
+                .map(Card::new)
+                .collect::<Result<Vec<Card>, CardsError>>()?,
+        })
     }
 
     fn len(&self) -> usize {
         self.cards.len()
     }
 
-    fn points(&self) -> u32 {
-        self.cards.iter().map(|x| x.points()).sum()
+    fn card(&self, cardnum: usize) -> Option<&Card> {
+        self.cards.iter().find(|card| card.cardnum == cardnum)
+    }
+
+    fn by_cardnum(&self) -> impl Iterator<Item = &Card> {
+        let mut cards = self.cards.iter().collect::<Vec<&Card>>();
+        cards.sort_by_key(|card| card.cardnum);
+        cards.into_iter()
+    }
+
+    fn points(&self, rule: &dyn ScoringRule) -> Result<u64, CardsError> {
+        self.cards.iter().try_fold(0_u64, |sum, card| {
+            sum.checked_add(card.points(rule)?)
+                .ok_or(CardsError::Overflow(card.cardnum))
+        })
+    }
+
+    // positions of the cards in `cards`, ordered by card number
+    fn positions_by_cardnum(&self) -> Result<Vec<usize>, CardsError> {
+        let mut positions: HashMap<usize, usize> = HashMap::new();
+
+        for (position, card) in self.cards.iter().enumerate() {
+            if positions.insert(card.cardnum, position).is_some() {
+                return Err(CardsError::DuplicateCard(card.cardnum));
+            }
+        }
+
+        // all card numbers are unique, so there is no gap if 1..=len are all there
+        (1..=self.len())
+            .map(|cardnum| {
+                positions
+                    .get(&cardnum)
+                    .copied()
+                    .ok_or(CardsError::MissingCard(cardnum))
+            })
+            .collect()
     }
 
-    fn calc_copies(mut self) -> Self {
-        for i in 0..self.len() {
-            for index in self.cards[i].cardnum as usize
-                ..self.cards[i].cardnum as usize + self.cards[i].wins() as usize
-            {
-                if index >= self.len() {
-                    break;
+    fn calc_copies(self, rule: &dyn ScoringRule, past_end: PastEnd) -> Result<Self, CardsError> {
+        self.cascade(rule, past_end, None)
+    }
+
+    // same as calc_copies, but also record which card won which copies
+    fn calc_copies_traced(
+        self,
+        rule: &dyn ScoringRule,
+        past_end: PastEnd,
+    ) -> Result<(Self, Trace), CardsError> {
+        let mut trace = Trace::default();
+        let cards = self.cascade(rule, past_end, Some(&mut trace))?;
+        Ok((cards, trace))
+    }
+
+    fn cascade(
+        mut self,
+        rule: &dyn ScoringRule,
+        past_end: PastEnd,
+        mut trace: Option<&mut Trace>,
+    ) -> Result<Self, CardsError> {
+        let positions = self.positions_by_cardnum()?;
+
+        // start over, so the same cards can be played with different rules
+        self.cards.iter_mut().for_each(|card| card.count = 1);
+
+        for (index, &position) in positions.iter().enumerate() {
+            let card = &self.cards[position];
+            let (cardnum, count) = (card.cardnum, card.count);
+
+            for (target, copies) in rule.copies(index, card.wins(), count, positions.len()) {
+                let Some(&target) = positions.get(target) else {
+                    match past_end {
+                        PastEnd::Ignore => continue,
+                        PastEnd::Reject => return Err(CardsError::PastEnd(cardnum)),
+                    }
+                };
+                let target = &mut self.cards[target];
+                target.count = target
+                    .count
+                    .checked_add(copies)
+                    .ok_or(CardsError::Overflow(target.cardnum))?;
+
+                if let Some(trace) = trace.as_mut() {
+                    trace.contributions.push(Contribution {
+                        from: cardnum,
+                        to: target.cardnum,
+                        copies,
+                    });
                 }
-                self.cards[index].count += self.cards[i].count;
             }
         }
-        self
+        Ok(self)
+    }
+
+    fn count(&self) -> Result<u64, CardsError> {
+        self.cards.iter().try_fold(0_u64, |sum, card| {
+            sum.checked_add(card.count)
+                .ok_or(CardsError::Overflow(card.cardnum))
+        })
     }
+}
 
-    fn count(&self) -> u32 {
-        self.cards.iter().map(|card| card.count).sum()
+// --explain <card>, --dot and --json show how the copies came to be
+fn print_trace(cards: &Cards, trace: &Trace, args: &[String]) {
+    if let Some(cardnum) = option(args, "--explain") {
+        let cardnum = cardnum
+            .parse::<usize>()
+            .expect("--explain needs a card number");
+        match trace.explain(cards, cardnum) {
+            Some(explanation) => println!("{}", explanation),
+            None => println!("there is no card {}", cardnum),
+        }
+    } else if args.iter().any(|arg| arg == "--dot") {
+        print!("{}", trace.to_dot(cards));
+    } else {
+        print!("{}", trace.to_json(cards));
     }
 }
 
 fn main() {
     let input = fs::read_to_string("4.input").expect("Should have been able to read the file");
-    let cards = Cards::new(&input).calc_copies();
+    let args = env::args().collect::<Vec<String>>();
+    let past_end = if args.iter().any(|arg| arg == "--strict") {
+        PastEnd::Reject
+    } else {
+        PastEnd::Ignore
+    };
+    let cards = Cards::new(&input).expect("Should have been a valid card table");
 
-    println!("total points: {}", cards.points());
-    println!("total cards: {}", cards.count());
+    // --rule <name> plays with other rules, --tournament plays all of them
+    let mut rules = rules();
+    if !args.iter().any(|arg| arg == "--tournament") {
+        let name = option(&args, "--rule").unwrap_or("standard");
+        rules.retain(|rule| rule.name() == name);
+        assert!(!rules.is_empty(), "unknown rule {}", name);
+    }
+
+    if args
+        .iter()
+        .any(|arg| ["--explain", "--dot", "--json"].contains(&arg.as_str()))
+    {
+        let (cards, trace) = cards
+            .calc_copies_traced(rules[0].as_ref(), past_end)
+            .expect("Should have been a valid card table");
+        print_trace(&cards, &trace, &args);
+        return;
+    }
+
+    for rule in rules {
+        let cards = cards
+            .clone()
+            .calc_copies(rule.as_ref(), past_end)
+            .expect("Should have been a valid card table");
+
+        println!("rule: {}", rule.name());
+        println!(
+            "total points: {}",
+            cards.points(rule.as_ref()).expect("points overflow")
+        );
+        println!(
+            "total cards: {}",
+            cards.count().expect("card count overflow")
+        );
+    }
 }
 
 #[cfg(test)]
@@ -105,11 +488,11 @@ mod tests {
     #[test]
     fn test_card_new() {
         assert_eq!(
-            Card::new("Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53"),
+            Card::new("Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53").unwrap(),
             Card {
                 cardnum: 1,
-                win: vec![41, 48, 83, 86, 17],
-                got: vec![83, 86, 6, 31, 17, 9, 48, 53],
+                win: NumberSet::new(&[41, 48, 83, 86, 17]),
+                wins: 4,
                 count: 1
             }
         );
@@ -118,13 +501,13 @@ mod tests {
     fn test_cards_new() {
         let input =
             fs::read_to_string("4sample.input").expect("Should have been able to read the file");
-        let cards = Cards::new(&input);
+        let cards = Cards::new(&input).unwrap();
         assert_eq!(
             cards.cards[0],
             Card {
                 cardnum: 1,
-                win: vec![41, 48, 83, 86, 17],
-                got: vec![83, 86, 6, 31, 17, 9, 48, 53],
+                win: NumberSet::new(&[41, 48, 83, 86, 17]),
+                wins: 4,
                 count: 1
             }
         );
@@ -134,25 +517,28 @@ mod tests {
 
     #[test]
     fn test_card_points() {
-        let card = Card::new("Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53");
+        let card = Card::new("Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53").unwrap();
 
-        assert_eq!(card.points(), 8);
+        assert_eq!(card.points(&Standard), Ok(8));
     }
 
     #[test]
     fn test_cards_points() {
         let input =
             fs::read_to_string("4sample.input").expect("Should have been able to read the file");
-        let cards = Cards::new(&input);
+        let cards = Cards::new(&input).unwrap();
 
-        assert_eq!(cards.points(), 13);
+        assert_eq!(cards.points(&Standard), Ok(13));
     }
 
     #[test]
     fn test_calc_copies() {
         let input =
             fs::read_to_string("4sample.input").expect("Should have been able to read the file");
-        let cards = Cards::new(&input).calc_copies();
+        let cards = Cards::new(&input)
+            .unwrap()
+            .calc_copies(&Standard, PastEnd::Ignore)
+            .unwrap();
 
         assert_eq!(cards.cards[0].count, 1);
         assert_eq!(cards.cards[1].count, 2);
@@ -166,9 +552,283 @@ mod tests {
     fn test_cards_count() {
         let input =
             fs::read_to_string("4sample.input").expect("Should have been able to read the file");
-        let cards = Cards::new(&input);
-        let cards = cards.calc_copies();
+        let cards = Cards::new(&input).unwrap();
+        let cards = cards.calc_copies(&Standard, PastEnd::Ignore).unwrap();
+
+        assert_eq!(cards.count(), Ok(30));
+    }
+
+    #[test]
+    fn test_calc_copies_out_of_order() {
+        let input = "Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19\n\
+                     Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1\n\
+                     Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53\n";
+        let cards = Cards::new(input)
+            .unwrap()
+            .calc_copies(&Standard, PastEnd::Ignore)
+            .unwrap();
+
+        assert_eq!(cards.cards[0].count, 2);
+        assert_eq!(cards.cards[1].count, 4);
+        assert_eq!(cards.cards[2].count, 1);
+    }
+
+    #[test]
+    fn test_calc_copies_invalid() {
+        let duplicate = "Card 1: 41 48 | 83 86\nCard 1: 13 32 | 61 30\n";
+        assert_eq!(
+            Cards::new(duplicate)
+                .unwrap()
+                .calc_copies(&Standard, PastEnd::Ignore)
+                .err(),
+            Some(CardsError::DuplicateCard(1))
+        );
+
+        let gap = "Card 1: 41 48 | 83 86\nCard 3: 13 32 | 61 30\n";
+        assert_eq!(
+            Cards::new(gap)
+                .unwrap()
+                .calc_copies(&Standard, PastEnd::Ignore)
+                .err(),
+            Some(CardsError::MissingCard(2))
+        );
+    }
+
+    #[test]
+    fn test_calc_copies_past_end() {
+        let input = "Card 1: 41 48 | 41 86\nCard 2: 13 32 | 13 32\n";
+
+        let cards = Cards::new(input)
+            .unwrap()
+            .calc_copies(&Standard, PastEnd::Ignore)
+            .unwrap();
+        assert_eq!(cards.count(), Ok(3));
+
+        assert_eq!(
+            Cards::new(input)
+                .unwrap()
+                .calc_copies(&Standard, PastEnd::Reject)
+                .err(),
+            Some(CardsError::PastEnd(2))
+        );
+    }
+
+    #[test]
+    fn test_card_new_invalid() {
+        assert_eq!(
+            Card::new("Card 1: 41 48 | 83 99999999999999999999"),
+            Err(CardsError::InvalidNumber(
+                "99999999999999999999".to_string()
+            ))
+        );
+        assert_eq!(
+            Card::new("Game 1: 41 48 | 83 86"),
+            Err(CardsError::InvalidLine("Game 1: 41 48 | 83 86".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_many_cards() {
+        let input = (1..=300)
+            .map(|cardnum| format!("Card {}: 1 | 2\n", cardnum))
+            .collect::<String>();
+        let cards = Cards::new(&input)
+            .unwrap()
+            .calc_copies(&Standard, PastEnd::Reject)
+            .unwrap();
+
+        assert_eq!(cards.cards[299].cardnum, 300);
+        assert_eq!(cards.count(), Ok(300));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let numbers = (1..=65)
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>()
+            .join(" ");
+        let card = Card::new(&format!("Card 1: {} | {}", numbers, numbers)).unwrap();
+        assert_eq!(card.points(&Standard), Err(CardsError::Overflow(1)));
+
+        // every card wins copies of all following cards, doubling the copies each time
+        let input = (1..=70)
+            .map(|cardnum| {
+                let numbers = (1..=70 - cardnum)
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" ");
+                format!("Card {}: 0 {} | {}\n", cardnum, numbers, numbers)
+            })
+            .collect::<String>();
+        let cards = Cards::new(&input).unwrap();
+        assert_eq!(
+            cards.calc_copies(&Standard, PastEnd::Ignore).err(),
+            Some(CardsError::Overflow(65))
+        );
+    }
+
+    #[test]
+    fn test_calc_copies_traced() {
+        let input =
+            fs::read_to_string("4sample.input").expect("Should have been able to read the file");
+        let (cards, trace) = Cards::new(&input)
+            .unwrap()
+            .calc_copies_traced(&Standard, PastEnd::Ignore)
+            .unwrap();
+
+        assert_eq!(cards.count(), Ok(30));
+        assert_eq!(
+            trace.contributions_to(5).collect::<Vec<&Contribution>>(),
+            [
+                &Contribution {
+                    from: 1,
+                    to: 5,
+                    copies: 1
+                },
+                &Contribution {
+                    from: 3,
+                    to: 5,
+                    copies: 4
+                },
+                &Contribution {
+                    from: 4,
+                    to: 5,
+                    copies: 8
+                },
+            ]
+        );
+        assert_eq!(
+            trace.explain(&cards, 5),
+            Some(
+                "card 5 has 14 copies: 1 original, 1 from card 1, 4 from card 3, 8 from card 4"
+                    .to_string()
+            )
+        );
+        assert_eq!(trace.explain(&cards, 7), None);
+    }
+
+    #[test]
+    fn test_trace_export() {
+        let input = "Card 1: 41 48 | 41 86\nCard 2: 13 32 | 61 30\n";
+        let (cards, trace) = Cards::new(input)
+            .unwrap()
+            .calc_copies_traced(&Standard, PastEnd::Reject)
+            .unwrap();
+
+        assert_eq!(
+            trace.to_dot(&cards),
+            "digraph cascade {\n\
+             \x20   card1 [label=\"card 1\\n1 copies\"];\n\
+             \x20   card2 [label=\"card 2\\n2 copies\"];\n\
+             \x20   card1 -> card2 [label=\"1\"];\n\
+             }\n"
+        );
+        assert_eq!(
+            trace.to_json(&cards),
+            "{\"cards\":[{\"card\":1,\"count\":1},{\"card\":2,\"count\":2}],\
+             \"contributions\":[{\"from\":1,\"to\":2,\"copies\":1}]}\n"
+        );
+    }
+
+    #[test]
+    fn test_rules_points() {
+        let input =
+            fs::read_to_string("4sample.input").expect("Should have been able to read the file");
+        let cards = Cards::new(&input).unwrap();
+
+        assert_eq!(cards.points(&Standard), Ok(13));
+        assert_eq!(cards.points(&Linear), Ok(9));
+        assert_eq!(Standard.points(65), None);
+    }
+
+    #[test]
+    fn test_rules_copies() {
+        let input =
+            fs::read_to_string("4sample.input").expect("Should have been able to read the file");
+        let cards = Cards::new(&input).unwrap();
+        let count = |rule: &dyn ScoringRule| {
+            cards
+                .clone()
+                .calc_copies(rule, PastEnd::Reject)
+                .map(|cards| {
+                    cards
+                        .cards
+                        .iter()
+                        .map(|card| card.count)
+                        .collect::<Vec<u64>>()
+                })
+        };
+
+        assert_eq!(count(&Standard), Ok(vec![1, 2, 4, 8, 14, 1]));
+        assert_eq!(count(&Linear), Ok(vec![1, 2, 4, 8, 14, 1]));
+        // card 1 only hands 1 copy to card 2, card 2 hands 2 copies to card 3 and 1 to card 4
+        let decay = |divisor| Decay {
+            divisor: NonZeroU64::new(divisor).unwrap(),
+        };
+        assert_eq!(count(&decay(2)), Ok(vec![1, 2, 3, 5, 7, 1]));
+        // nothing decays by 1
+        assert_eq!(count(&decay(1)), Ok(vec![1, 2, 4, 8, 14, 1]));
+        assert_eq!(count(&Wrap), Ok(vec![1, 2, 4, 8, 14, 1]));
+    }
+
+    #[test]
+    fn test_rule_wrap() {
+        let input = "Card 1: 41 48 | 83 86\nCard 2: 13 32 | 13 32\nCard 3: 1 2 | 1 5\n";
+        let cards = Cards::new(input).unwrap();
+
+        assert_eq!(
+            cards.clone().calc_copies(&Standard, PastEnd::Reject).err(),
+            Some(CardsError::PastEnd(2))
+        );
+        // card 2 wins copies of cards 3 and 1, card 3 wins 2 more copies of card 1
+        let cards = cards.calc_copies(&Wrap, PastEnd::Reject).unwrap();
+        assert_eq!(
+            cards
+                .cards
+                .iter()
+                .map(|card| card.count)
+                .collect::<Vec<u64>>(),
+            [4, 1, 2]
+        );
+    }
+
+    #[test]
+    fn test_number_set() {
+        let small = NumberSet::new(&[0, 17, 64, 99]);
+        assert!(matches!(small, NumberSet::Bits(_)));
+        assert!(small.contains(0));
+        assert!(small.contains(64));
+        assert!(!small.contains(63));
+        assert!(!small.contains(1000));
+        assert!(!small.contains(u64::MAX));
+
+        // a single large number doesn't need a large bitset
+        assert!(matches!(NumberSet::new(&[65535]), NumberSet::Sorted(_)));
+        assert!(matches!(NumberSet::new(&[1024]), NumberSet::Bits(_)));
+
+        let large = NumberSet::new(&[5, u64::MAX]);
+        assert!(matches!(large, NumberSet::Sorted(_)));
+        assert!(large.contains(5));
+        assert!(large.contains(u64::MAX));
+        assert!(!large.contains(6));
+
+        let card = Card::new("Card 1: 18446744073709551615 3 | 3 18446744073709551615 4").unwrap();
+        assert_eq!(card.wins(), 2);
+    }
+
+    #[test]
+    fn test_large_card_set() {
+        // every card wins one copy of the next card, so card n ends up with n copies
+        let len = 100_000;
+        let input = (1..=len)
+            .map(|cardnum| format!("Card {}: 41 48 83 86 17 | 83 6 31 9 53\n", cardnum))
+            .collect::<String>();
+        let cards = Cards::new(&input)
+            .unwrap()
+            .calc_copies(&Standard, PastEnd::Ignore)
+            .unwrap();
 
-        assert_eq!(cards.count(), 30);
+        assert_eq!(cards.points(&Standard), Ok(len));
+        assert_eq!(cards.count(), Ok(len * (len + 1) / 2));
     }
 }
@@ -0,0 +1,810 @@
+use crate::args::option;
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+
+// digit words of each built-in language, from zero to nine
+const LANGUAGES: [(&str, [&str; 10]); 4] = [
+    (
+        "english",
+        [
+            "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+        ],
+    ),
+    (
+        "german",
+        [
+            "null", "eins", "zwei", "drei", "vier", "fünf", "sechs", "sieben", "acht", "neun",
+        ],
+    ),
+    (
+        "french",
+        [
+            "zéro", "un", "deux", "trois", "quatre", "cinq", "six", "sept", "huit", "neuf",
+        ],
+    ),
+    (
+        "spanish",
+        [
+            "cero", "uno", "dos", "tres", "cuatro", "cinco", "seis", "siete", "ocho", "nueve",
+        ],
+    ),
+];
+
+#[derive(Debug, PartialEq)]
+pub enum VocabularyError {
+    // line of a vocabulary file is not `<words> = <value>`
+    InvalidLine(usize),
+    // no built-in vocabulary of that language
+    UnknownLanguage(String),
+}
+
+// words the scanner looks for and the numbers they stand for, a word may also be several words
+// like "twenty one"
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Vocabulary {
+    words: Vec<(String, u32)>,
+}
+
+impl Vocabulary {
+    // 0 to 9 written as digits
+    pub fn digits() -> Self {
+        Vocabulary {
+            words: (0..10).map(|digit| (digit.to_string(), digit)).collect(),
+        }
+    }
+
+    // the digit words of a built-in language, zero only if asked for as the puzzle has none
+    pub fn language(name: &str, zero: bool) -> Result<Self, VocabularyError> {
+        let (_, words) = LANGUAGES
+            .iter()
+            .find(|(language, _)| *language == name)
+            .ok_or_else(|| VocabularyError::UnknownLanguage(name.to_string()))?;
+
+        Ok(Vocabulary {
+            words: (0..)
+                .zip(words)
+                .skip(if zero { 0 } else { 1 })
+                .map(|(value, word)| (word.to_string(), value))
+                .collect(),
+        })
+    }
+
+    // one `<words> = <value>` per line, empty lines and lines starting with # are skipped:
+    // # danish
+    // en = 1
+    // enogtyve = 21
+    pub fn parse(value: &str) -> Result<Self, VocabularyError> {
+        let mut words = vec![];
+
+        for (number, line) in value.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || VocabularyError::InvalidLine(number + 1);
+            let (word, value) = line.split_once('=').ok_or_else(invalid)?;
+            let word = word.trim();
+            if word.is_empty() {
+                return Err(invalid());
+            }
+            words.push((
+                word.to_string(),
+                value.trim().parse().map_err(|_| invalid())?,
+            ));
+        }
+        Ok(Vocabulary { words })
+    }
+
+    // the words of both, e.g. digits and the words of a language
+    pub fn with(mut self, other: Vocabulary) -> Self {
+        self.words.extend(other.words);
+        self
+    }
+
+    pub fn words(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.words
+            .iter()
+            .map(|(word, value)| (word.as_str(), *value))
+    }
+}
+
+// how a number is written
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Source {
+    Digit,
+    Word,
+}
+
+impl Source {
+    fn of(word: &str) -> Self {
+        if word.bytes().all(|byte| byte.is_ascii_digit()) {
+            Source::Digit
+        } else {
+            Source::Word
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Source::Digit => "digit",
+            Source::Word => "word",
+        }
+    }
+}
+
+// a number found in a line, start..end are byte positions
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Token {
+    pub value: u32,
+    pub start: usize,
+    pub end: usize,
+    pub source: Source,
+}
+
+impl Token {
+    // the digit the number starts with, e.g. 2 of twenty one
+    pub fn first_digit(&self) -> u32 {
+        let mut value = self.value;
+        while value >= 10 {
+            value /= 10;
+        }
+        value
+    }
+
+    // the digit the number ends with, e.g. 1 of twenty one
+    pub fn last_digit(&self) -> u32 {
+        self.value % 10
+    }
+}
+
+// state of the automaton, the root is the state without any matched bytes
+#[derive(Debug, Default)]
+struct Node {
+    // state after the next byte, the missing ones go back to the root
+    next: HashMap<u8, usize>,
+    // length, value and source of every word ending in this state, longest first
+    words: Vec<(usize, u32, Source)>,
+}
+
+// finds every word of a vocabulary in a single pass over a line, also the ones overlapping each
+// other like eight and two in "eightwo"
+#[derive(Debug)]
+pub struct Scanner {
+    nodes: Vec<Node>,
+}
+
+impl Scanner {
+    pub fn new(vocabulary: &Vocabulary) -> Self {
+        let mut nodes = vec![Node::default()];
+        // children of each state, to build the transitions of the automaton from the trie
+        let mut children: Vec<Vec<(u8, usize)>> = vec![vec![]];
+
+        for (word, value) in vocabulary.words() {
+            let mut state = 0;
+            for &byte in word.as_bytes() {
+                state = match nodes[state].next.get(&byte) {
+                    Some(&child) => child,
+                    None => {
+                        nodes.push(Node::default());
+                        children.push(vec![]);
+                        let child = nodes.len() - 1;
+                        nodes[state].next.insert(byte, child);
+                        children[state].push((byte, child));
+                        child
+                    }
+                };
+            }
+            nodes[state]
+                .words
+                .push((word.len(), value, Source::of(word)));
+        }
+
+        // breadth first, so the fallback of a state is done before the state: the fallback is
+        // the state of the longest proper suffix that is in the trie
+        let mut fallback = vec![0; nodes.len()];
+        let mut queue = children[0]
+            .iter()
+            .map(|&(_, child)| child)
+            .collect::<VecDeque<usize>>();
+
+        while let Some(state) = queue.pop_front() {
+            let inherited = nodes[fallback[state]].words.clone();
+            nodes[state].words.extend(inherited);
+            nodes[state]
+                .words
+                .sort_by_key(|&(length, value, _)| Reverse((length, value)));
+            nodes[state]
+                .words
+                .dedup_by_key(|&mut (length, _, _)| length);
+
+            for &(byte, child) in &children[state] {
+                fallback[child] = nodes[fallback[state]].next.get(&byte).copied().unwrap_or(0);
+                queue.push_back(child);
+            }
+            // missing transitions continue like the fallback does
+            let missing = nodes[fallback[state]]
+                .next
+                .iter()
+                .filter(|(byte, _)| !nodes[state].next.contains_key(byte))
+                .map(|(&byte, &next)| (byte, next))
+                .collect::<Vec<(u8, usize)>>();
+            nodes[state].next.extend(missing);
+        }
+
+        Scanner { nodes }
+    }
+
+    // every word in the line, ordered by their end, longest first
+    pub fn tokens<'a>(&'a self, line: &'a str) -> impl Iterator<Item = Token> + 'a {
+        line.bytes()
+            .enumerate()
+            .scan(0, |state, (pos, byte)| {
+                *state = self.nodes[*state].next.get(&byte).copied().unwrap_or(0);
+                Some((pos + 1, &self.nodes[*state].words))
+            })
+            .flat_map(|(end, words)| {
+                words.iter().map(move |&(length, value, source)| Token {
+                    value,
+                    start: end - length,
+                    end,
+                    source,
+                })
+            })
+    }
+
+    // the word starting first and the one ending last, the longer one if they start or end at
+    // the same byte
+    pub fn first_last(&self, line: &str) -> Option<(Token, Token)> {
+        let mut tokens = self.tokens(line);
+        let token = tokens.next()?;
+
+        Some(tokens.fold((token, token), |(first, last), token| {
+            (
+                if token.start < first.start
+                    || (token.start == first.start && token.end > first.end)
+                {
+                    token
+                } else {
+                    first
+                },
+                if token.end > last.end { token } else { last },
+            )
+        }))
+    }
+
+    // the numbers in the line by their start, without the ones inside of longer ones like one
+    // in "twenty one"
+    pub fn numbers(&self, line: &str) -> Vec<Token> {
+        let mut tokens = self.tokens(line).collect::<Vec<Token>>();
+        tokens.sort_by_key(|token| (token.start, Reverse(token.end)));
+
+        let mut result: Vec<Token> = vec![];
+        for token in tokens {
+            // a token inside another one comes after it, and the previous one reaches furthest
+            if result
+                .last()
+                .is_none_or(|previous| previous.end < token.end)
+            {
+                result.push(token);
+            }
+        }
+        result
+    }
+}
+
+// why a line with digits has no value
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ExtractionError {
+    // the value does not fit into an u64
+    Overflow,
+    // the strategy needs other digits, e.g. a pair out of a single digit
+    NotApplicable,
+}
+
+// how the numbers of a line make up its calibration value
+pub trait Extraction {
+    fn name(&self) -> String;
+
+    // value of the numbers in a line, ordered by their start; None if there are no digits
+    fn value(&self, numbers: &[Token]) -> Result<Option<u64>, ExtractionError>;
+}
+
+// digits of the numbers, e.g. 2, 1 and 5 for "twenty one five"
+fn digits(numbers: &[Token]) -> Vec<u64> {
+    numbers
+        .iter()
+        .flat_map(|number| {
+            number
+                .value
+                .to_string()
+                .chars()
+                .filter_map(|c| c.to_digit(10))
+                .map(u64::from)
+                .collect::<Vec<u64>>()
+        })
+        .collect()
+}
+
+// the digits written one after the other, None without digits
+fn concat(digits: &[u64]) -> Result<Option<u64>, ExtractionError> {
+    if digits.is_empty() {
+        return Ok(None);
+    }
+    digits
+        .iter()
+        .try_fold(0_u64, |value, &digit| {
+            value.checked_mul(10)?.checked_add(digit)
+        })
+        .map(Some)
+        .ok_or(ExtractionError::Overflow)
+}
+
+// first and last digit, the puzzle's rule
+pub struct FirstLast;
+
+impl Extraction for FirstLast {
+    fn name(&self) -> String {
+        "first-last".to_string()
+    }
+
+    fn value(&self, numbers: &[Token]) -> Result<Option<u64>, ExtractionError> {
+        let (Some(first), Some(last)) = (
+            numbers.first(),
+            numbers.iter().max_by_key(|number| number.end),
+        ) else {
+            return Ok(None);
+        };
+        Ok(Some(u64::from(
+            first.first_digit() * 10 + last.last_digit(),
+        )))
+    }
+}
+
+// all digits one after the other
+pub struct Concat;
+
+impl Extraction for Concat {
+    fn name(&self) -> String {
+        "concat".to_string()
+    }
+
+    fn value(&self, numbers: &[Token]) -> Result<Option<u64>, ExtractionError> {
+        concat(&digits(numbers))
+    }
+}
+
+// largest number of two digits in the order of the line, e.g. 93 for 1923
+pub struct LargestPair;
+
+impl Extraction for LargestPair {
+    fn name(&self) -> String {
+        "largest-pair".to_string()
+    }
+
+    fn value(&self, numbers: &[Token]) -> Result<Option<u64>, ExtractionError> {
+        let digits = digits(numbers);
+        let Some(&first) = digits.first() else {
+            return Ok(None);
+        };
+        let mut largest_first = first;
+
+        digits
+            .iter()
+            .skip(1)
+            .map(|&digit| {
+                let pair = largest_first * 10 + digit;
+                largest_first = largest_first.max(digit);
+                pair
+            })
+            .max()
+            .map(Some)
+            .ok_or(ExtractionError::NotApplicable)
+    }
+}
+
+pub struct SumOfDigits;
+
+impl Extraction for SumOfDigits {
+    fn name(&self) -> String {
+        "sum".to_string()
+    }
+
+    fn value(&self, numbers: &[Token]) -> Result<Option<u64>, ExtractionError> {
+        let digits = digits(numbers);
+        if digits.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(digits.iter().sum()))
+        }
+    }
+}
+
+// the last k digits, all of them if there are fewer
+pub struct LastDigits {
+    pub k: usize,
+}
+
+impl Extraction for LastDigits {
+    fn name(&self) -> String {
+        format!("last/{}", self.k)
+    }
+
+    fn value(&self, numbers: &[Token]) -> Result<Option<u64>, ExtractionError> {
+        let digits = digits(numbers);
+        if self.k == 0 && !digits.is_empty() {
+            return Err(ExtractionError::NotApplicable);
+        }
+        concat(&digits[digits.len().saturating_sub(self.k)..])
+    }
+}
+
+// first-last, concat, largest-pair, sum or last/<k>
+pub fn extraction(name: &str) -> Option<Box<dyn Extraction>> {
+    match name {
+        "first-last" => Some(Box::new(FirstLast)),
+        "concat" => Some(Box::new(Concat)),
+        "largest-pair" => Some(Box::new(LargestPair)),
+        "sum" => Some(Box::new(SumOfDigits)),
+        _ => {
+            let k = name.strip_prefix("last/")?.parse().ok()?;
+            Some(Box::new(LastDigits { k }))
+        }
+    }
+}
+
+// --extract <name>, first-last if not given
+pub fn extraction_from_args(args: &[String]) -> Box<dyn Extraction> {
+    match option(args, "--extract") {
+        Some(name) => extraction(name)
+            .expect("--extract needs first-last, concat, largest-pair, sum or last/<k>"),
+        None => Box::new(FirstLast),
+    }
+}
+
+// what to do with lines without a value, e.g. without any digit
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Missing {
+    // fail on the first one
+    Strict,
+    // leave them out
+    Skip,
+    // count them as 0
+    Zero,
+    // leave them out, but list them
+    Report,
+}
+
+impl Missing {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "strict" => Some(Missing::Strict),
+            "skip" => Some(Missing::Skip),
+            "zero" => Some(Missing::Zero),
+            "report" => Some(Missing::Report),
+            _ => None,
+        }
+    }
+
+    // --missing strict|skip|zero|report, strict if not given
+    pub fn from_args(args: &[String]) -> Self {
+        match option(args, "--missing") {
+            Some(name) => {
+                Missing::from_name(name).expect("--missing needs strict, skip, zero or report")
+            }
+            None => Missing::Strict,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CalibrationError {
+    // line, counted from 1, has no digit
+    NoDigit(usize),
+    // line has digits, but the extraction gives no value for them
+    NoValue(usize),
+    // value of the line does not fit into an u64
+    Overflow(usize),
+    // sum of the values does not fit into an u64
+    SumOverflow,
+}
+
+// values of the lines and the lines without digits if they are reported, lines counted from 1
+#[derive(Debug, PartialEq)]
+pub struct Calibration {
+    pub values: Vec<(usize, u64)>,
+    pub missing: Vec<usize>,
+}
+
+impl Calibration {
+    // calibrate makes sure it fits
+    pub fn sum(&self) -> u64 {
+        self.values.iter().map(|(_, value)| value).sum()
+    }
+}
+
+// calibration value of every line, None for lines without digits; only those go by missing
+pub fn calibrate(
+    input: &str,
+    missing: Missing,
+    value: impl Fn(&str) -> Result<Option<u64>, ExtractionError>,
+) -> Result<Calibration, CalibrationError> {
+    let mut result = Calibration {
+        values: vec![],
+        missing: vec![],
+    };
+
+    for (pos, line) in input.split_terminator('\n').enumerate() {
+        let number = pos + 1;
+        let value = value(line).map_err(|error| match error {
+            ExtractionError::Overflow => CalibrationError::Overflow(number),
+            ExtractionError::NotApplicable => CalibrationError::NoValue(number),
+        })?;
+        match (value, missing) {
+            (Some(value), _) => result.values.push((number, value)),
+            (None, Missing::Strict) => return Err(CalibrationError::NoDigit(number)),
+            (None, Missing::Skip) => (),
+            (None, Missing::Zero) => result.values.push((number, 0)),
+            (None, Missing::Report) => result.missing.push(number),
+        }
+    }
+
+    result
+        .values
+        .iter()
+        .try_fold(0_u64, |sum, &(_, value)| sum.checked_add(value))
+        .ok_or(CalibrationError::SumOverflow)?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calibration::*;
+
+    fn values(scanner: &Scanner, line: &str) -> Vec<(u32, usize, usize)> {
+        scanner
+            .tokens(line)
+            .map(|token| (token.value, token.start, token.end))
+            .collect()
+    }
+
+    fn english() -> Scanner {
+        Scanner::new(&Vocabulary::digits().with(Vocabulary::language("english", false).unwrap()))
+    }
+
+    #[test]
+    fn test_tokens() {
+        let scanner = english();
+
+        assert_eq!(
+            values(&scanner, "eightwo3"),
+            [(8, 0, 5), (2, 4, 7), (3, 7, 8)]
+        );
+        assert_eq!(
+            values(&scanner, "xtwone3four"),
+            [(2, 1, 4), (1, 3, 6), (3, 6, 7), (4, 7, 11)]
+        );
+        // seven and nine share the n
+        assert_eq!(values(&scanner, "sevenine"), [(7, 0, 5), (9, 4, 8)]);
+        assert_eq!(values(&scanner, "oonne"), []);
+        assert_eq!(
+            values(&Scanner::new(&Vocabulary::digits()), "one2"),
+            [(2, 3, 4)]
+        );
+    }
+
+    #[test]
+    fn test_nested_words() {
+        let scanner = Scanner::new(&Vocabulary::parse("abcde = 1\nbc = 2\nc = 3\n").unwrap());
+
+        assert_eq!(values(&scanner, "abcde"), [(2, 1, 3), (3, 2, 3), (1, 0, 5)]);
+        assert_eq!(
+            scanner
+                .first_last("abcde")
+                .map(|(first, last)| (first.value, last.value)),
+            Some((1, 1))
+        );
+        assert_eq!(
+            scanner
+                .first_last("xbcx")
+                .map(|(first, last)| (first.value, last.value)),
+            Some((2, 2))
+        );
+    }
+
+    #[test]
+    fn test_first_last() {
+        let scanner = english();
+        let first_last = |line| {
+            scanner
+                .first_last(line)
+                .map(|(first, last)| (first.value, last.value))
+        };
+
+        assert_eq!(first_last("two1nine"), Some((2, 9)));
+        assert_eq!(first_last("zoneight234"), Some((1, 4)));
+        assert_eq!(first_last("8twoneh"), Some((8, 1)));
+        assert_eq!(first_last("7pqrstsixteen"), Some((7, 6)));
+        assert_eq!(first_last("nothing"), None);
+
+        let (first, last) = scanner.first_last("two1").unwrap();
+        assert_eq!((first.source, last.source), (Source::Word, Source::Digit));
+    }
+
+    #[test]
+    fn test_vocabulary() {
+        let german = Vocabulary::language("german", true).unwrap();
+        assert_eq!(german.words().count(), 10);
+        assert_eq!(german.words().nth(5), Some(("fünf", 5)));
+        assert_eq!(
+            Vocabulary::language("spanish", false)
+                .unwrap()
+                .words()
+                .count(),
+            9
+        );
+        assert_eq!(
+            Vocabulary::language("klingon", false),
+            Err(VocabularyError::UnknownLanguage("klingon".to_string()))
+        );
+
+        assert_eq!(
+            Vocabulary::parse("# danish\n\nen = 1\n  to=2\n"),
+            Ok(Vocabulary {
+                words: vec![("en".to_string(), 1), ("to".to_string(), 2)]
+            })
+        );
+        assert_eq!(
+            Vocabulary::parse("en = 1\nto\n"),
+            Err(VocabularyError::InvalidLine(2))
+        );
+        assert_eq!(
+            Vocabulary::parse(" = 1\n"),
+            Err(VocabularyError::InvalidLine(1))
+        );
+        assert_eq!(
+            Vocabulary::parse("en = een\n"),
+            Err(VocabularyError::InvalidLine(1))
+        );
+    }
+
+    #[test]
+    fn test_languages() {
+        let scanner = |name| {
+            Scanner::new(&Vocabulary::digits().with(Vocabulary::language(name, true).unwrap()))
+        };
+        let first_last = |scanner: &Scanner, line| {
+            scanner
+                .first_last(line)
+                .map(|(first, last)| (first.value, last.value))
+        };
+
+        // fünf is more than one byte per char
+        let german = scanner("german");
+        assert_eq!(first_last(&german, "xfünfzweinullx"), Some((5, 0)));
+        assert_eq!(german.tokens("fünf").next().map(|token| token.end), Some(5));
+        assert_eq!(first_last(&scanner("french"), "zérotroisix"), Some((0, 6)));
+        assert_eq!(first_last(&scanner("spanish"), "doscero7uno"), Some((2, 1)));
+        assert_eq!(first_last(&scanner("english"), "zerone"), Some((0, 1)));
+    }
+
+    #[test]
+    fn test_multi_word() {
+        let scanner =
+            Scanner::new(&Vocabulary::parse("twenty one = 21\none = 1\nfive = 5\n").unwrap());
+        let (first, last) = scanner.first_last("twenty one and five").unwrap();
+
+        assert_eq!((first.value, first.start, first.end), (21, 0, 10));
+        assert_eq!((first.first_digit(), last.last_digit()), (2, 5));
+        // one is part of twenty one, but the number starts before it
+        let (first, last) = scanner.first_last("five twenty one").unwrap();
+        assert_eq!((first.value, last.value), (5, 21));
+        assert_eq!((first.first_digit(), last.last_digit()), (5, 1));
+    }
+
+    #[test]
+    fn test_calibrate() {
+        let scanner = Scanner::new(&Vocabulary::digits());
+        let value = |line: &str| FirstLast.value(&scanner.numbers(line));
+        let input = "a1b2\n\nno digits\n7\n";
+
+        assert_eq!(
+            calibrate(input, Missing::Strict, value),
+            Err(CalibrationError::NoDigit(2))
+        );
+        assert_eq!(
+            calibrate(input, Missing::Skip, value),
+            Ok(Calibration {
+                values: vec![(1, 12), (4, 77)],
+                missing: vec![]
+            })
+        );
+        assert_eq!(
+            calibrate(input, Missing::Zero, value).unwrap().values,
+            [(1, 12), (2, 0), (3, 0), (4, 77)]
+        );
+        let report = calibrate(input, Missing::Report, value).unwrap();
+        assert_eq!((report.sum(), report.missing), (89, vec![2, 3]));
+        assert_eq!(
+            calibrate("12\n", Missing::Strict, value).map(|calibration| calibration.sum()),
+            Ok(12)
+        );
+        assert_eq!(
+            calibrate("1\n1\n", Missing::Strict, |_| Ok(Some(u64::MAX))),
+            Err(CalibrationError::SumOverflow)
+        );
+
+        // lines with digits but without a value never count as missing
+        let input = format!("12\n{}\n", "9".repeat(25));
+        for missing in [
+            Missing::Strict,
+            Missing::Skip,
+            Missing::Zero,
+            Missing::Report,
+        ] {
+            assert_eq!(
+                calibrate(&input, missing, |line| Concat.value(&scanner.numbers(line))),
+                Err(CalibrationError::Overflow(2))
+            );
+            assert_eq!(
+                calibrate("12\n7\n", missing, |line| LargestPair
+                    .value(&scanner.numbers(line))),
+                Err(CalibrationError::NoValue(2))
+            );
+        }
+        assert_eq!(Missing::from_name("zero"), Some(Missing::Zero));
+        assert_eq!(Missing::from_name("lenient"), None);
+    }
+
+    #[test]
+    fn test_numbers() {
+        let scanner = Scanner::new(
+            &Vocabulary::digits()
+                .with(Vocabulary::parse("twenty one = 21\none = 1\ntwo = 2\neight = 8\n").unwrap()),
+        );
+
+        assert_eq!(
+            scanner
+                .numbers("eightwo twenty one1")
+                .iter()
+                .map(|number| number.value)
+                .collect::<Vec<u32>>(),
+            [8, 2, 21, 1]
+        );
+    }
+
+    #[test]
+    fn test_extraction() {
+        let scanner = Scanner::new(
+            &Vocabulary::digits()
+                .with(Vocabulary::language("english", false).unwrap())
+                .with(Vocabulary::parse("twenty one = 21\n").unwrap()),
+        );
+        let value =
+            |name: &str, line: &str| extraction(name).unwrap().value(&scanner.numbers(line));
+
+        assert_eq!(value("first-last", "two1nine"), Ok(Some(29)));
+        assert_eq!(value("first-last", "twenty one"), Ok(Some(21)));
+        assert_eq!(value("concat", "two1nine"), Ok(Some(219)));
+        assert_eq!(value("concat", "eightwo twenty one"), Ok(Some(8221)));
+        assert_eq!(
+            value("concat", &"9".repeat(20)),
+            Err(ExtractionError::Overflow)
+        );
+        assert_eq!(value("largest-pair", "1923"), Ok(Some(93)));
+        assert_eq!(value("largest-pair", "91"), Ok(Some(91)));
+        assert_eq!(
+            value("largest-pair", "7"),
+            Err(ExtractionError::NotApplicable)
+        );
+        assert_eq!(value("sum", "two1nine"), Ok(Some(12)));
+        assert_eq!(value("last/2", "12three4"), Ok(Some(34)));
+        assert_eq!(value("last/5", "12"), Ok(Some(12)));
+        assert_eq!(value("last/0", "12"), Err(ExtractionError::NotApplicable));
+        for name in ["first-last", "concat", "largest-pair", "sum", "last/3"] {
+            assert_eq!(extraction(name).unwrap().name(), name);
+            assert_eq!(value(name, "nothing"), Ok(None));
+        }
+        assert!(extraction("last/x").is_none());
+        assert!(extraction("median").is_none());
+    }
+}
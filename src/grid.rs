@@ -0,0 +1,609 @@
+use std::collections::VecDeque;
+use std::ops::{Index, IndexMut};
+
+// position of a cell, x is the column and y the row
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Pos {
+    pub x: usize,
+    pub y: usize,
+}
+
+impl Pos {
+    pub fn new(x: usize, y: usize) -> Self {
+        Pos { x, y }
+    }
+
+    // None if the result would be left of or above the origin
+    pub fn offset(&self, dx: isize, dy: isize) -> Option<Pos> {
+        Some(Pos {
+            x: self.x.checked_add_signed(dx)?,
+            y: self.y.checked_add_signed(dy)?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GridError {
+    // row has a different length than the first one
+    RaggedRow { y: usize, len: usize, width: usize },
+    // neighborhood mask without exactly one center
+    MaskCenter(usize),
+}
+
+// which cells are next to a cell
+#[derive(Debug, PartialEq, Clone)]
+pub enum Neighborhood {
+    // cells at most this many steps up, down, left or right
+    VonNeumann(usize),
+    // cells in the square of this radius
+    Moore(usize),
+    // offsets (dx, dy) from the cell
+    Custom(Vec<(isize, isize)>),
+}
+
+impl Neighborhood {
+    // example, @ is the cell and # its neighbors:
+    // .#.
+    // #@#
+    // .#.
+    pub fn from_mask(mask: &str) -> Result<Self, GridError> {
+        let grid = Grid::parse(mask)?;
+        let centers = grid
+            .positions()
+            .filter(|&pos| grid[pos] == '@')
+            .collect::<Vec<Pos>>();
+        let [center] = centers[..] else {
+            return Err(GridError::MaskCenter(centers.len()));
+        };
+
+        Ok(Neighborhood::Custom(
+            grid.positions()
+                .filter(|&pos| grid[pos] == '#')
+                .map(|pos| {
+                    (
+                        pos.x as isize - center.x as isize,
+                        pos.y as isize - center.y as isize,
+                    )
+                })
+                .collect(),
+        ))
+    }
+
+    // offsets (dx, dy) of the neighbors, row by row, never (0, 0)
+    pub fn offsets(&self) -> Vec<(isize, isize)> {
+        let square = |r: usize| {
+            let r = r as isize;
+            (-r..=r).flat_map(move |dy| (-r..=r).map(move |dx| (dx, dy)))
+        };
+
+        match self {
+            Neighborhood::VonNeumann(r) => square(*r)
+                .filter(|(dx, dy)| dx.unsigned_abs() + dy.unsigned_abs() <= *r)
+                .filter(|&offset| offset != (0, 0))
+                .collect(),
+            Neighborhood::Moore(r) => square(*r).filter(|&offset| offset != (0, 0)).collect(),
+            Neighborhood::Custom(offsets) => {
+                let mut offsets = offsets.clone();
+                offsets.retain(|&offset| offset != (0, 0));
+                offsets.sort_by_key(|&(dx, dy)| (dy, dx));
+                offsets.dedup();
+                offsets
+            }
+        }
+    }
+
+    // largest distance of a neighbor in x or y
+    pub fn reach(&self) -> usize {
+        self.offsets()
+            .iter()
+            .map(|(dx, dy)| dx.unsigned_abs().max(dy.unsigned_abs()))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+// connected cells of a grid
+#[derive(Debug, PartialEq, Clone)]
+pub struct Region {
+    pub id: usize,
+    pub size: usize,
+    // top left and bottom right corner of the bounding box, both inclusive
+    pub min: Pos,
+    pub max: Pos,
+}
+
+// rectangular grid, stored row by row
+#[derive(Debug, PartialEq, Clone)]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    cells: Vec<T>,
+}
+
+impl<T> Grid<T> {
+    pub fn new(width: usize, height: usize, value: T) -> Self
+    where
+        T: Clone,
+    {
+        Grid {
+            width,
+            height,
+            cells: vec![value; width * height],
+        }
+    }
+
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Result<Self, GridError> {
+        let width = rows.first().map_or(0, |row| row.len());
+        let height = rows.len();
+        let mut cells = Vec::with_capacity(width * height);
+
+        for (y, row) in rows.into_iter().enumerate() {
+            if row.len() != width {
+                return Err(GridError::RaggedRow {
+                    y,
+                    len: row.len(),
+                    width,
+                });
+            }
+            cells.extend(row);
+        }
+
+        Ok(Grid {
+            width,
+            height,
+            cells,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn contains(&self, pos: Pos) -> bool {
+        pos.x < self.width && pos.y < self.height
+    }
+
+    pub fn get(&self, pos: Pos) -> Option<&T> {
+        if self.contains(pos) {
+            Some(&self.cells[pos.y * self.width + pos.x])
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, pos: Pos) -> Option<&mut T> {
+        if self.contains(pos) {
+            Some(&mut self.cells[pos.y * self.width + pos.x])
+        } else {
+            None
+        }
+    }
+
+    // cell at a position that may be outside of the grid, also left of or above it
+    pub fn get_signed(&self, x: isize, y: isize) -> Option<&T> {
+        self.get(Pos::new(0, 0).offset(x, y)?)
+    }
+
+    // cell at a position that may be outside of the grid, `default` if it is
+    pub fn get_or(&self, x: isize, y: isize, default: T) -> T
+    where
+        T: Copy,
+    {
+        self.get_signed(x, y).copied().unwrap_or(default)
+    }
+
+    pub fn set(&mut self, pos: Pos, value: T) {
+        self[pos] = value;
+    }
+
+    pub fn row(&self, y: usize) -> &[T] {
+        &self.cells[y * self.width..(y + 1) * self.width]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        (0..self.height).map(|y| self.row(y))
+    }
+
+    pub fn column(&self, x: usize) -> impl Iterator<Item = &T> {
+        assert!(x < self.width, "column {} out of range", x);
+        self.cells[x..].iter().step_by(self.width)
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = impl Iterator<Item = &T>> {
+        (0..self.width).map(|x| self.column(x))
+    }
+
+    // all positions, row by row
+    pub fn positions(&self) -> impl Iterator<Item = Pos> {
+        let width = self.width;
+        (0..self.height).flat_map(move |y| (0..width).map(move |x| Pos::new(x, y)))
+    }
+
+    // the up to 4 orthogonal neighbors inside the grid
+    pub fn neighbors4(&self, pos: Pos) -> impl Iterator<Item = Pos> + '_ {
+        self.neighbors(pos, &Neighborhood::VonNeumann(1))
+    }
+
+    // the up to 8 orthogonal and diagonal neighbors inside the grid
+    pub fn neighbors8(&self, pos: Pos) -> impl Iterator<Item = Pos> + '_ {
+        self.neighbors(pos, &Neighborhood::Moore(1))
+    }
+
+    // the neighbors inside the grid
+    pub fn neighbors(
+        &self,
+        pos: Pos,
+        neighborhood: &Neighborhood,
+    ) -> impl Iterator<Item = Pos> + '_ {
+        neighborhood
+            .offsets()
+            .into_iter()
+            .filter_map(move |(dx, dy)| pos.offset(dx, dy))
+            .filter(|&pos| self.contains(pos))
+    }
+
+    // cells connected to start through neighbors that are members, start first, nothing if start
+    // is no member
+    pub fn flood_fill(
+        &self,
+        start: Pos,
+        neighborhood: &Neighborhood,
+        member: impl Fn(&T) -> bool,
+    ) -> Vec<Pos> {
+        let mut visited = Grid::new(self.width, self.height, false);
+        self.fill(start, neighborhood, &member, &mut visited)
+    }
+
+    // breadth first from start, skipping and marking visited cells
+    fn fill(
+        &self,
+        start: Pos,
+        neighborhood: &Neighborhood,
+        member: &impl Fn(&T) -> bool,
+        visited: &mut Grid<bool>,
+    ) -> Vec<Pos> {
+        if !self.get(start).is_some_and(member) || visited[start] {
+            return vec![];
+        }
+        let mut result = vec![];
+        let mut queue = VecDeque::from([start]);
+        visited.set(start, true);
+
+        while let Some(pos) = queue.pop_front() {
+            result.push(pos);
+            for next in self.neighbors(pos, neighborhood) {
+                if !visited[next] && member(&self[next]) {
+                    visited.set(next, true);
+                    queue.push_back(next);
+                }
+            }
+        }
+        result
+    }
+
+    // id of the region of each member cell, regions numbered row by row by their first cell,
+    // the neighborhood should be symmetric for the regions to be well defined
+    pub fn label_regions(
+        &self,
+        neighborhood: &Neighborhood,
+        member: impl Fn(&T) -> bool,
+    ) -> (Grid<Option<usize>>, Vec<Region>) {
+        let mut labels = Grid::new(self.width, self.height, None);
+        let mut visited = Grid::new(self.width, self.height, false);
+        let mut regions: Vec<Region> = vec![];
+
+        for start in self.positions() {
+            let cells = self.fill(start, neighborhood, &member, &mut visited);
+            if cells.is_empty() {
+                continue;
+            }
+
+            let id = regions.len();
+            let mut region = Region {
+                id,
+                size: cells.len(),
+                min: start,
+                max: start,
+            };
+            for pos in cells {
+                region.min = Pos::new(region.min.x.min(pos.x), region.min.y.min(pos.y));
+                region.max = Pos::new(region.max.x.max(pos.x), region.max.y.max(pos.y));
+                labels.set(pos, Some(id));
+            }
+            regions.push(region);
+        }
+        (labels, regions)
+    }
+
+    pub fn map<U>(&self, f: impl Fn(&T) -> U) -> Grid<U> {
+        Grid {
+            width: self.width,
+            height: self.height,
+            cells: self.cells.iter().map(f).collect(),
+        }
+    }
+
+    // build a grid of the given size, taking each cell from this grid
+    fn remap(&self, width: usize, height: usize, source: impl Fn(Pos) -> Pos) -> Grid<T>
+    where
+        T: Clone,
+    {
+        let cells = (0..height)
+            .flat_map(|y| (0..width).map(move |x| Pos::new(x, y)))
+            .map(|pos| self[source(pos)].clone())
+            .collect();
+
+        Grid {
+            width,
+            height,
+            cells,
+        }
+    }
+
+    // swap rows and columns
+    pub fn transpose(&self) -> Grid<T>
+    where
+        T: Clone,
+    {
+        self.remap(self.height, self.width, |pos| Pos::new(pos.y, pos.x))
+    }
+
+    pub fn rotate_clockwise(&self) -> Grid<T>
+    where
+        T: Clone,
+    {
+        let height = self.height;
+        self.remap(self.height, self.width, |pos| {
+            Pos::new(pos.y, height - 1 - pos.x)
+        })
+    }
+
+    pub fn rotate_counterclockwise(&self) -> Grid<T>
+    where
+        T: Clone,
+    {
+        let width = self.width;
+        self.remap(self.height, self.width, |pos| {
+            Pos::new(width - 1 - pos.y, pos.x)
+        })
+    }
+
+    // mirror left to right
+    pub fn flip_horizontal(&self) -> Grid<T>
+    where
+        T: Clone,
+    {
+        let width = self.width;
+        self.remap(self.width, self.height, |pos| {
+            Pos::new(width - 1 - pos.x, pos.y)
+        })
+    }
+
+    // mirror top to bottom
+    pub fn flip_vertical(&self) -> Grid<T>
+    where
+        T: Clone,
+    {
+        let height = self.height;
+        self.remap(self.width, self.height, |pos| {
+            Pos::new(pos.x, height - 1 - pos.y)
+        })
+    }
+}
+
+impl Grid<char> {
+    // one row per line, one cell per char
+    pub fn parse(value: &str) -> Result<Self, GridError> {
+        Self::from_rows(
+            value
+                .split_terminator('\n')
+                .map(|line| line.chars().collect())
+                .collect(),
+        )
+    }
+}
+
+impl<T> Index<Pos> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, pos: Pos) -> &T {
+        self.get(pos)
+            .unwrap_or_else(|| panic!("{:?} out of range", pos))
+    }
+}
+
+impl<T> IndexMut<Pos> for Grid<T> {
+    fn index_mut(&mut self, pos: Pos) -> &mut T {
+        self.get_mut(pos)
+            .unwrap_or_else(|| panic!("{:?} out of range", pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grid::*;
+
+    fn sample() -> Grid<char> {
+        Grid::parse("abc\ndef\n").unwrap()
+    }
+
+    fn to_string(grid: &Grid<char>) -> String {
+        grid.rows()
+            .map(|row| row.iter().collect::<String>() + "\n")
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let grid = sample();
+
+        assert_eq!(grid.width(), 3);
+        assert_eq!(grid.height(), 2);
+        assert_eq!(grid[Pos::new(2, 1)], 'f');
+        assert_eq!(
+            Grid::parse("abc\nde\n"),
+            Err(GridError::RaggedRow {
+                y: 1,
+                len: 2,
+                width: 3
+            })
+        );
+        assert_eq!(Grid::parse("").unwrap().width(), 0);
+    }
+
+    #[test]
+    fn test_get() {
+        let mut grid = sample();
+
+        assert_eq!(grid.get(Pos::new(0, 0)), Some(&'a'));
+        assert_eq!(grid.get(Pos::new(3, 0)), None);
+        assert_eq!(grid.get(Pos::new(0, 2)), None);
+        assert_eq!(grid.get_signed(-1, 0), None);
+        assert_eq!(grid.get_signed(1, 1), Some(&'e'));
+        assert_eq!(grid.get_or(-1, -1, '.'), '.');
+        assert_eq!(grid.get_or(2, 0, '.'), 'c');
+
+        grid.set(Pos::new(1, 0), 'x');
+        assert_eq!(to_string(&grid), "axc\ndef\n");
+    }
+
+    #[test]
+    fn test_rows_columns() {
+        let grid = sample();
+
+        assert_eq!(grid.row(1), ['d', 'e', 'f']);
+        assert_eq!(grid.column(1).collect::<String>(), "be");
+        assert_eq!(
+            grid.columns()
+                .map(|column| column.collect::<String>())
+                .collect::<Vec<String>>(),
+            ["ad", "be", "cf"]
+        );
+        assert_eq!(
+            grid.positions().map(|pos| grid[pos]).collect::<String>(),
+            "abcdef"
+        );
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let grid = sample();
+
+        assert_eq!(
+            grid.neighbors4(Pos::new(0, 0)).collect::<Vec<Pos>>(),
+            [Pos::new(1, 0), Pos::new(0, 1)]
+        );
+        assert_eq!(
+            grid.neighbors8(Pos::new(1, 1))
+                .map(|pos| grid[pos])
+                .collect::<String>(),
+            "abcdf"
+        );
+    }
+
+    #[test]
+    fn test_neighborhood() {
+        assert_eq!(
+            Neighborhood::VonNeumann(1).offsets(),
+            [(0, -1), (-1, 0), (1, 0), (0, 1)]
+        );
+        assert_eq!(Neighborhood::VonNeumann(2).offsets().len(), 12);
+        assert_eq!(Neighborhood::Moore(1).offsets().len(), 8);
+        assert_eq!(Neighborhood::Moore(2).offsets().len(), 24);
+        assert_eq!(Neighborhood::Moore(2).reach(), 2);
+        assert_eq!(
+            Neighborhood::Custom(vec![(1, 0), (0, 0), (-1, 0), (1, 0)]).offsets(),
+            [(-1, 0), (1, 0)]
+        );
+        assert_eq!(
+            Neighborhood::from_mask("#..\n.@.\n..#\n"),
+            Ok(Neighborhood::Custom(vec![(-1, -1), (1, 1)]))
+        );
+        assert_eq!(
+            Neighborhood::from_mask("@.@\n"),
+            Err(GridError::MaskCenter(2))
+        );
+
+        let grid = sample();
+        assert_eq!(
+            grid.neighbors(Pos::new(0, 0), &Neighborhood::Moore(2))
+                .map(|pos| grid[pos])
+                .collect::<String>(),
+            "bcdef"
+        );
+    }
+
+    #[test]
+    fn test_regions() {
+        let grid = Grid::parse("#..#\n#.##\n..#.\n#...\n").unwrap();
+        let member = |&c: &char| c == '#';
+
+        assert_eq!(
+            grid.flood_fill(Pos::new(3, 0), &Neighborhood::VonNeumann(1), member),
+            [
+                Pos::new(3, 0),
+                Pos::new(3, 1),
+                Pos::new(2, 1),
+                Pos::new(2, 2)
+            ]
+        );
+        assert_eq!(
+            grid.flood_fill(Pos::new(1, 0), &Neighborhood::VonNeumann(1), member),
+            []
+        );
+
+        let (labels, regions) = grid.label_regions(&Neighborhood::VonNeumann(1), member);
+        assert_eq!(
+            regions,
+            [
+                Region {
+                    id: 0,
+                    size: 2,
+                    min: Pos::new(0, 0),
+                    max: Pos::new(0, 1)
+                },
+                Region {
+                    id: 1,
+                    size: 4,
+                    min: Pos::new(2, 0),
+                    max: Pos::new(3, 2)
+                },
+                Region {
+                    id: 2,
+                    size: 1,
+                    min: Pos::new(0, 3),
+                    max: Pos::new(0, 3)
+                },
+            ]
+        );
+        assert_eq!(labels.row(1), [Some(0), None, Some(1), Some(1)]);
+
+        // the regions are not diagonally next to each other, but within two cells
+        let (_, regions) = grid.label_regions(&Neighborhood::Moore(1), member);
+        assert_eq!(
+            regions
+                .iter()
+                .map(|region| region.size)
+                .collect::<Vec<usize>>(),
+            [2, 4, 1]
+        );
+        let (_, regions) = grid.label_regions(&Neighborhood::Moore(2), member);
+        assert_eq!(regions.len(), 1);
+    }
+
+    #[test]
+    fn test_transform() {
+        let grid = sample();
+
+        assert_eq!(to_string(&grid.transpose()), "ad\nbe\ncf\n");
+        assert_eq!(to_string(&grid.rotate_clockwise()), "da\neb\nfc\n");
+        assert_eq!(to_string(&grid.rotate_counterclockwise()), "cf\nbe\nad\n");
+        assert_eq!(to_string(&grid.flip_horizontal()), "cba\nfed\n");
+        assert_eq!(to_string(&grid.flip_vertical()), "def\nabc\n");
+        assert_eq!(grid.map(|&c| c == 'a').row(0), [true, false, false]);
+    }
+}
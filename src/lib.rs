@@ -0,0 +1,5 @@
+// code shared by several days
+
+pub mod args;
+pub mod calibration;
+pub mod grid;